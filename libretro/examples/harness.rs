@@ -0,0 +1,191 @@
+// Minimal libretro frontend loading the core as a shared library:
+//
+//     cargo build -p chip8_libretro
+//     cargo run -p chip8_libretro --example harness -- target/debug/libchip8_libretro.so [rom.ch8] [frames]
+//
+// Without a ROM a built-in program draws the 0 glyph. Prints the framebuffer
+// of the last frame as text and checks that save states round trip.
+use libloading::{Library, Symbol};
+use std::env;
+use std::ffi::CStr;
+use std::fs;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::process;
+use std::ptr;
+use std::sync::Mutex;
+
+#[repr(C)]
+struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct AvInfo {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+// Last frame as text, one line per row
+static FRAME: Mutex<String> = Mutex::new(String::new());
+static AUDIO_FRAMES: Mutex<usize> = Mutex::new(0);
+
+extern "C" fn environment(_cmd: c_uint, _data: *mut c_void) -> bool {
+    // Accept the pixel format, no core options are set
+    true
+}
+
+extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    let mut frame = FRAME.lock().unwrap();
+    frame.clear();
+
+    for row in 0..height as usize {
+        let line = unsafe { (data as *const u8).add(row * pitch) as *const u32 };
+        for col in 0..width as usize {
+            let pixel = unsafe { *line.add(col) };
+            frame.push(if pixel & 0x00FF_FFFF != 0 { '#' } else { '.' });
+        }
+        frame.push('\n');
+    }
+}
+
+extern "C" fn audio_sample(_left: i16, _right: i16) {}
+
+extern "C" fn audio_sample_batch(_data: *const i16, frames: usize) -> usize {
+    *AUDIO_FRAMES.lock().unwrap() += frames;
+    frames
+}
+
+extern "C" fn input_poll() {}
+
+extern "C" fn input_state(_port: c_uint, _device: c_uint, _index: c_uint, _id: c_uint) -> i16 {
+    0
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: harness <core.so> [rom.ch8] [frames]");
+        process::exit(2);
+    }
+
+    let rom = match args.get(2) {
+        Some(path) => fs::read(path).unwrap(),
+        None => vec![0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06],
+    };
+    let frames: usize = args.get(3).map_or(60, |f| f.parse().unwrap());
+
+    unsafe {
+        let lib = Library::new(&args[1]).unwrap();
+
+        let api_version: Symbol<extern "C" fn() -> c_uint> = lib.get(b"retro_api_version").unwrap();
+        let get_system_info: Symbol<unsafe extern "C" fn(*mut SystemInfo)> =
+            lib.get(b"retro_get_system_info").unwrap();
+        let get_av_info: Symbol<unsafe extern "C" fn(*mut AvInfo)> =
+            lib.get(b"retro_get_system_av_info").unwrap();
+        let set_environment: Symbol<extern "C" fn(extern "C" fn(c_uint, *mut c_void) -> bool)> =
+            lib.get(b"retro_set_environment").unwrap();
+        let set_video_refresh: Symbol<
+            extern "C" fn(extern "C" fn(*const c_void, c_uint, c_uint, usize)),
+        > = lib.get(b"retro_set_video_refresh").unwrap();
+        let set_audio_sample: Symbol<extern "C" fn(extern "C" fn(i16, i16))> =
+            lib.get(b"retro_set_audio_sample").unwrap();
+        let set_audio_sample_batch: Symbol<
+            extern "C" fn(extern "C" fn(*const i16, usize) -> usize),
+        > = lib.get(b"retro_set_audio_sample_batch").unwrap();
+        let set_input_poll: Symbol<extern "C" fn(extern "C" fn())> =
+            lib.get(b"retro_set_input_poll").unwrap();
+        let set_input_state: Symbol<
+            extern "C" fn(extern "C" fn(c_uint, c_uint, c_uint, c_uint) -> i16),
+        > = lib.get(b"retro_set_input_state").unwrap();
+        let init: Symbol<extern "C" fn()> = lib.get(b"retro_init").unwrap();
+        let load_game: Symbol<unsafe extern "C" fn(*const GameInfo) -> bool> =
+            lib.get(b"retro_load_game").unwrap();
+        let run: Symbol<extern "C" fn()> = lib.get(b"retro_run").unwrap();
+        let serialize_size: Symbol<extern "C" fn() -> usize> =
+            lib.get(b"retro_serialize_size").unwrap();
+        let serialize: Symbol<unsafe extern "C" fn(*mut c_void, usize) -> bool> =
+            lib.get(b"retro_serialize").unwrap();
+        let unserialize: Symbol<unsafe extern "C" fn(*const c_void, usize) -> bool> =
+            lib.get(b"retro_unserialize").unwrap();
+        let unload_game: Symbol<extern "C" fn()> = lib.get(b"retro_unload_game").unwrap();
+        let deinit: Symbol<extern "C" fn()> = lib.get(b"retro_deinit").unwrap();
+
+        let mut info = SystemInfo {
+            library_name: ptr::null(),
+            library_version: ptr::null(),
+            valid_extensions: ptr::null(),
+            need_fullpath: false,
+            block_extract: false,
+        };
+        get_system_info(&mut info);
+        println!(
+            "{} {} (API {}), extensions: {}",
+            CStr::from_ptr(info.library_name).to_string_lossy(),
+            CStr::from_ptr(info.library_version).to_string_lossy(),
+            api_version(),
+            CStr::from_ptr(info.valid_extensions).to_string_lossy()
+        );
+
+        set_environment(environment);
+        set_video_refresh(video_refresh);
+        set_audio_sample(audio_sample);
+        set_audio_sample_batch(audio_sample_batch);
+        set_input_poll(input_poll);
+        set_input_state(input_state);
+        init();
+
+        let game = GameInfo {
+            path: ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: ptr::null(),
+        };
+        assert!(load_game(&game), "retro_load_game failed");
+
+        let mut av_info = AvInfo::default();
+        get_av_info(&mut av_info);
+        println!(
+            "{}x{} at {} fps, {} Hz audio",
+            av_info.base_width, av_info.base_height, av_info.fps, av_info.sample_rate
+        );
+
+        for _ in 0..frames {
+            run();
+        }
+
+        let mut state = vec![0_u8; serialize_size()];
+        assert!(serialize(state.as_mut_ptr() as *mut c_void, state.len()));
+        let frame = FRAME.lock().unwrap().clone();
+        run();
+        assert!(unserialize(state.as_ptr() as *const c_void, state.len()));
+        println!("Save state of {} bytes restored", state.len());
+
+        println!(
+            "{} frames, {} audio frames",
+            frames + 1,
+            *AUDIO_FRAMES.lock().unwrap()
+        );
+        print!("{}", frame);
+
+        unload_game();
+        deinit();
+    }
+}
@@ -0,0 +1,687 @@
+// libretro core, loadable by RetroArch and other libretro frontends:
+//
+//     cargo build -p chip8_libretro --release
+//     retroarch -L target/release/libchip8_libretro.so game.ch8
+//
+// The frontend calls in from one thread at a time, the state lives in
+// a global since the API has no handle to pass around.
+use chip8_core::chip8::{KEY_SIZE, MEM_SIZE, STATE_SIZE};
+use chip8_core::palette::Palette;
+use chip8_core::platform::Quirks;
+use chip8_core::{Chip8, SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const RETRO_API_VERSION: c_uint = 1;
+
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_DEVICE_KEYBOARD: c_uint = 3;
+
+const RETRO_DEVICE_ID_JOYPAD_B: c_uint = 0;
+const RETRO_DEVICE_ID_JOYPAD_Y: c_uint = 1;
+const RETRO_DEVICE_ID_JOYPAD_SELECT: c_uint = 2;
+const RETRO_DEVICE_ID_JOYPAD_START: c_uint = 3;
+const RETRO_DEVICE_ID_JOYPAD_UP: c_uint = 4;
+const RETRO_DEVICE_ID_JOYPAD_DOWN: c_uint = 5;
+const RETRO_DEVICE_ID_JOYPAD_LEFT: c_uint = 6;
+const RETRO_DEVICE_ID_JOYPAD_RIGHT: c_uint = 7;
+const RETRO_DEVICE_ID_JOYPAD_A: c_uint = 8;
+const RETRO_DEVICE_ID_JOYPAD_X: c_uint = 9;
+const RETRO_DEVICE_ID_JOYPAD_L: c_uint = 10;
+const RETRO_DEVICE_ID_JOYPAD_R: c_uint = 11;
+
+const RETRO_MEMORY_SYSTEM_RAM: c_uint = 2;
+const RETRO_REGION_NTSC: c_uint = 0;
+
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+const FRAME_RATE: u32 = 60;
+const SAMPLE_RATE: u32 = 44_100;
+const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / FRAME_RATE) as usize;
+const BEEP_FREQUENCY: u32 = 440;
+const BEEP_AMPLITUDE: i16 = 4_000;
+const DEFAULT_IPS: u32 = 700;
+
+// Joypad buttons mapped to the hex keypad, arrows follow the common 2/4/6/8 layout
+const JOYPAD_KEYS: [(c_uint, usize); 12] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x5),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x0),
+    (RETRO_DEVICE_ID_JOYPAD_X, 0x1),
+    (RETRO_DEVICE_ID_JOYPAD_Y, 0x3),
+    (RETRO_DEVICE_ID_JOYPAD_L, 0x7),
+    (RETRO_DEVICE_ID_JOYPAD_R, 0x9),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, 0xA),
+    (RETRO_DEVICE_ID_JOYPAD_START, 0xF),
+];
+
+// Keyboard keys (RETROK_*, ASCII) in hex keypad order, same layout as the SDL frontend
+const KEYBOARD_KEYS: [u8; KEY_SIZE] = [
+    b'x', b'1', b'2', b'3', b'q', b'w', b'e', b'a', b's', b'd', b'z', b'c', b'4', b'r', b'f', b'v',
+];
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    pub library_name: *const c_char,
+    pub library_version: *const c_char,
+    pub valid_extensions: *const c_char,
+    pub need_fullpath: bool,
+    pub block_extract: bool,
+}
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    pub base_width: c_uint,
+    pub base_height: c_uint,
+    pub max_width: c_uint,
+    pub max_height: c_uint,
+    pub aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    pub fps: f64,
+    pub sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    pub geometry: RetroGameGeometry,
+    pub timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    pub path: *const c_char,
+    pub data: *const c_void,
+    pub size: usize,
+    pub meta: *const c_char,
+}
+
+#[repr(C)]
+struct RetroVariable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+pub type RetroEnvironment = extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+pub type RetroVideoRefresh =
+    extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type RetroAudioSample = extern "C" fn(left: i16, right: i16);
+pub type RetroAudioSampleBatch = extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type RetroInputPoll = extern "C" fn();
+pub type RetroInputState =
+    extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<RetroEnvironment>,
+    video_refresh: Option<RetroVideoRefresh>,
+    audio_sample_batch: Option<RetroAudioSampleBatch>,
+    input_poll: Option<RetroInputPoll>,
+    input_state: Option<RetroInputState>,
+}
+
+struct Core {
+    chip8: Chip8,
+
+    // Kept to reload it on retro_reset()
+    rom: Vec<u8>,
+
+    ips: u32,
+    quirks: Quirks,
+
+    // Remainder of instructions when ips isn't a multiple of the frame rate
+    budget: u32,
+
+    // XRGB8888 framebuffer handed to the frontend
+    video: [u32; SCREEN_WIDTH * SCREEN_HEIGHT],
+
+    // Interleaved stereo samples of one frame
+    audio: [i16; SAMPLES_PER_FRAME * 2],
+
+    // Phase of the beep in samples
+    phase: u32,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+fn callbacks() -> Callbacks {
+    *CALLBACKS.lock().unwrap()
+}
+
+impl Core {
+    fn new(rom: Vec<u8>, ips: u32, quirks: Quirks) -> Option<Core> {
+        let mut core = Core {
+            chip8: Chip8::init(),
+            rom,
+            ips,
+            quirks,
+            budget: 0,
+            video: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            audio: [0; SAMPLES_PER_FRAME * 2],
+            phase: 0,
+        };
+
+        core.power_on().then_some(core)
+    }
+
+    // Fresh machine with the ROM loaded
+    fn power_on(&mut self) -> bool {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        self.chip8 = Chip8::init();
+        self.chip8.set_quirks(self.quirks);
+        self.chip8.set_seed(seed);
+        // retro_run is called at 60 Hz, the timers tick once per call
+        self.chip8.set_timers_per_frame(true);
+        self.budget = 0;
+
+        self.chip8.load_rom(&self.rom).is_ok()
+    }
+
+    fn run(&mut self, callbacks: &Callbacks) {
+        if let Some(poll) = callbacks.input_poll {
+            poll();
+        }
+
+        if let Some(state) = callbacks.input_state {
+            let mut pressed = [false; KEY_SIZE];
+            for (id, key) in JOYPAD_KEYS.iter() {
+                pressed[*key] |= state(0, RETRO_DEVICE_JOYPAD, 0, *id) != 0;
+            }
+            for (key, retrok) in KEYBOARD_KEYS.iter().enumerate() {
+                pressed[key] |= state(0, RETRO_DEVICE_KEYBOARD, 0, *retrok as c_uint) != 0;
+            }
+
+            for (key, pressed) in pressed.iter().enumerate() {
+                if *pressed != self.chip8.is_key_pressed(key) {
+                    self.chip8.set_key(key, *pressed);
+                }
+            }
+        }
+
+        self.budget += self.ips;
+        self.chip8.run_frame(self.budget / FRAME_RATE);
+        self.budget %= FRAME_RATE;
+
+        if let Some(video_refresh) = callbacks.video_refresh {
+            for (pixel, value) in self.video.iter_mut().zip(self.chip8.screen()) {
+                let [r, g, b] = Palette::DEFAULT.color(*value);
+                *pixel = u32::from_be_bytes([0, r, g, b]);
+            }
+            video_refresh(
+                self.video.as_ptr() as *const c_void,
+                SCREEN_WIDTH as c_uint,
+                SCREEN_HEIGHT as c_uint,
+                SCREEN_WIDTH * 4,
+            );
+        }
+
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            let half_period = SAMPLE_RATE / BEEP_FREQUENCY / 2;
+            let beeping = self.chip8.is_beeping();
+
+            for frame in self.audio.chunks_mut(2) {
+                let sample = if !beeping {
+                    0
+                } else if (self.phase / half_period).is_multiple_of(2) {
+                    BEEP_AMPLITUDE
+                } else {
+                    -BEEP_AMPLITUDE
+                };
+                frame[0] = sample;
+                frame[1] = sample;
+                self.phase = self.phase.wrapping_add(1);
+            }
+            audio_sample_batch(self.audio.as_ptr(), SAMPLES_PER_FRAME);
+        }
+    }
+}
+
+// Core options shown by the frontend, "description; default|other values"
+const VARIABLES: [(&[u8], &[u8]); 2] = [
+    (
+        b"chip8_ips\0",
+        b"Instructions per second; 700|500|600|800|1000|1500|2000|120|240|360\0",
+    ),
+    (b"chip8_quirks\0", b"Quirks; default|vip|schip|xochip\0"),
+];
+
+fn get_variable(environment: RetroEnvironment, key: &[u8]) -> Option<String> {
+    let mut variable = RetroVariable {
+        key: key.as_ptr() as *const c_char,
+        value: ptr::null(),
+    };
+
+    if !environment(
+        RETRO_ENVIRONMENT_GET_VARIABLE,
+        &mut variable as *mut RetroVariable as *mut c_void,
+    ) || variable.value.is_null()
+    {
+        return None;
+    }
+
+    let value = unsafe { CStr::from_ptr(variable.value) };
+    value.to_str().ok().map(String::from)
+}
+
+// Instructions per second and quirks from the core options
+fn read_variables(callbacks: &Callbacks) -> (u32, Quirks) {
+    let mut ips = DEFAULT_IPS;
+    let mut quirks = Quirks::DEFAULT;
+
+    if let Some(environment) = callbacks.environment {
+        if let Some(value) = get_variable(environment, VARIABLES[0].0) {
+            ips = value.parse().unwrap_or(DEFAULT_IPS);
+        }
+        if let Some(value) = get_variable(environment, VARIABLES[1].0) {
+            quirks = value.parse().unwrap_or_default();
+        }
+    }
+
+    (ips, quirks)
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// # Safety
+///
+/// `info` must point to a writable `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: b"chip8_rust\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` must point to a writable `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: SCREEN_WIDTH as c_uint,
+            base_height: SCREEN_HEIGHT as c_uint,
+            max_width: SCREEN_WIDTH as c_uint,
+            max_height: SCREEN_HEIGHT as c_uint,
+            aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+        },
+        timing: RetroSystemTiming {
+            fps: FRAME_RATE as f64,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(environment: RetroEnvironment) {
+    CALLBACKS.lock().unwrap().environment = Some(environment);
+
+    let mut variables: Vec<RetroVariable> = VARIABLES
+        .iter()
+        .map(|(key, value)| RetroVariable {
+            key: key.as_ptr() as *const c_char,
+            value: value.as_ptr() as *const c_char,
+        })
+        .collect();
+    variables.push(RetroVariable {
+        key: ptr::null(),
+        value: ptr::null(),
+    });
+    environment(
+        RETRO_ENVIRONMENT_SET_VARIABLES,
+        variables.as_mut_ptr() as *mut c_void,
+    );
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(video_refresh: RetroVideoRefresh) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(video_refresh);
+}
+
+// Unused, audio is sent in batches
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_audio_sample: RetroAudioSample) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(audio_sample_batch: RetroAudioSampleBatch) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(audio_sample_batch);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(input_poll: RetroInputPoll) {
+    CALLBACKS.lock().unwrap().input_poll = Some(input_poll);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(input_state: RetroInputState) {
+    CALLBACKS.lock().unwrap().input_state = Some(input_state);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.power_on();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = callbacks();
+
+    if let Some(environment) = callbacks.environment {
+        let mut updated = false;
+        environment(
+            RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE,
+            &mut updated as *mut bool as *mut c_void,
+        );
+
+        if updated {
+            let (ips, quirks) = read_variables(&callbacks);
+            if let Some(core) = CORE.lock().unwrap().as_mut() {
+                core.ips = ips;
+                core.quirks = quirks;
+                core.chip8.set_quirks(quirks);
+            }
+        }
+    }
+
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.run(&callbacks);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    STATE_SIZE
+}
+
+/// # Safety
+///
+/// `data` must point to `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) if !data.is_null() => {
+            let buf = slice::from_raw_parts_mut(data as *mut u8, size);
+            core.chip8.save_state(buf).is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// # Safety
+///
+/// `data` must point to `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) if !data.is_null() => {
+            let buf = slice::from_raw_parts(data as *const u8, size);
+            core.chip8.load_state(buf).is_ok()
+        }
+        _ => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+///
+/// `game` must be null or point to a valid `retro_game_info` whose `data`
+/// holds `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+
+    let callbacks = callbacks();
+    if let Some(environment) = callbacks.environment {
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+        if !environment(
+            RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+            &mut format as *mut c_uint as *mut c_void,
+        ) {
+            return false;
+        }
+    }
+
+    let rom = slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+    let (ips, quirks) = read_variables(&callbacks);
+    let core = Core::new(rom, ips, quirks);
+    let loaded = core.is_some();
+
+    *CORE.lock().unwrap() = core;
+    loaded
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match CORE.lock().unwrap().as_mut() {
+        Some(core) if id == RETRO_MEMORY_SYSTEM_RAM => {
+            core.chip8.memory_mut().as_mut_ptr() as *mut c_void
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    if id == RETRO_MEMORY_SYSTEM_RAM {
+        MEM_SIZE
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The core is global, so tests must not run concurrently
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+    static LAST_PIXEL: Mutex<u32> = Mutex::new(0);
+    static PRESSED: Mutex<Option<c_uint>> = Mutex::new(None);
+
+    extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+        if cmd == RETRO_ENVIRONMENT_GET_VARIABLE {
+            let variable = unsafe { &mut *(data as *mut RetroVariable) };
+            let key = unsafe { CStr::from_ptr(variable.key) };
+            if key.to_bytes() == b"chip8_ips" {
+                variable.value = b"120\0".as_ptr() as *const c_char;
+                return true;
+            }
+            return false;
+        }
+        true
+    }
+
+    extern "C" fn video_refresh(
+        data: *const c_void,
+        _width: c_uint,
+        _height: c_uint,
+        _pitch: usize,
+    ) {
+        *LAST_PIXEL.lock().unwrap() = unsafe { *(data as *const u32) };
+    }
+
+    extern "C" fn input_state(_port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+        (device == RETRO_DEVICE_JOYPAD && *PRESSED.lock().unwrap() == Some(id)) as i16
+    }
+
+    fn load(rom: &[u8]) -> bool {
+        retro_set_environment(environment);
+        retro_set_video_refresh(video_refresh);
+        retro_set_input_state(input_state);
+
+        let game = RetroGameInfo {
+            path: ptr::null(),
+            data: rom.as_ptr() as *const c_void,
+            size: rom.len(),
+            meta: ptr::null(),
+        };
+        unsafe { retro_load_game(&game) }
+    }
+
+    fn with_core<F: FnOnce(&mut Core)>(f: F) {
+        f(CORE.lock().unwrap().as_mut().unwrap())
+    }
+
+    #[test]
+    fn test_load_game_reads_variables() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        assert!(load(&[0x12, 0x00]));
+        with_core(|core| assert_eq!(core.ips, 120));
+        assert!(!unsafe { retro_load_game(ptr::null()) });
+        assert!(!load(&[0; MEM_SIZE]));
+    }
+
+    #[test]
+    fn test_run_draws_frame() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Draw the 0 glyph at (0, 0), then loop forever
+        assert!(load(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06]));
+        // 2 instructions per frame at 120 instructions per second
+        retro_run();
+        retro_run();
+
+        assert_eq!(*LAST_PIXEL.lock().unwrap(), 0x00FF_FFFF);
+    }
+
+    #[test]
+    fn test_timers_per_frame() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Set the delay timer to 10, then loop forever
+        assert!(load(&[0x60, 0x0A, 0xF0, 0x15, 0x12, 0x04]));
+        retro_run();
+        retro_run();
+        with_core(|core| assert_eq!(core.chip8.delay_timer(), 8));
+
+        retro_reset();
+        retro_run();
+        with_core(|core| {
+            core.chip8.step();
+            assert_eq!(core.chip8.delay_timer(), 9);
+        });
+    }
+
+    #[test]
+    fn test_input() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Wait for a key into v0
+        assert!(load(&[0xF0, 0x0A, 0x12, 0x02]));
+        retro_run();
+        *PRESSED.lock().unwrap() = Some(RETRO_DEVICE_ID_JOYPAD_UP);
+        retro_run();
+        *PRESSED.lock().unwrap() = None;
+
+        with_core(|core| {
+            assert_eq!(core.chip8.v()[0], 0x2);
+            assert!(!core.chip8.is_waiting_for_key());
+        });
+    }
+
+    #[test]
+    fn test_serialize() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        assert!(load(&[0x70, 0x01, 0x12, 0x00]));
+        let mut state = vec![0_u8; retro_serialize_size()];
+
+        retro_run();
+        assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, state.len()) });
+        let mut v0 = 0;
+        with_core(|core| v0 = core.chip8.v()[0]);
+
+        retro_run();
+        assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, state.len()) });
+        with_core(|core| assert_eq!(core.chip8.v()[0], v0));
+
+        assert!(!unsafe { retro_unserialize(state.as_ptr() as *const c_void, 10) });
+    }
+
+    #[test]
+    fn test_reset_and_memory() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        assert!(load(&[0x70, 0x01, 0x12, 0x00]));
+        retro_run();
+        retro_reset();
+        with_core(|core| assert_eq!(core.chip8.v()[0], 0));
+
+        let memory = retro_get_memory_data(RETRO_MEMORY_SYSTEM_RAM) as *const u8;
+        assert_eq!(unsafe { *memory.add(0x200) }, 0x70);
+        assert_eq!(retro_get_memory_size(RETRO_MEMORY_SYSTEM_RAM), MEM_SIZE);
+        assert!(retro_get_memory_data(0).is_null());
+    }
+}
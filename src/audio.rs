@@ -0,0 +1,62 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+const BEEP_FREQUENCY: f32 = 440.0;
+const BEEP_VOLUME: f32 = 0.1;
+
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = if self.phase <= 0.5 {
+                BEEP_VOLUME
+            } else {
+                -BEEP_VOLUME
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+// Plays the beep while the sound timer is active
+pub struct Beeper {
+    device: AudioDevice<SquareWave>,
+    playing: bool,
+}
+
+impl Beeper {
+    pub fn new(audio_subsystem: &AudioSubsystem) -> Result<Beeper, String> {
+        let spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio_subsystem.open_playback(None, &spec, |spec| SquareWave {
+            phase_inc: BEEP_FREQUENCY / spec.freq as f32,
+            phase: 0.0,
+        })?;
+
+        Ok(Beeper {
+            device,
+            playing: false,
+        })
+    }
+
+    pub fn set_beeping(&mut self, beeping: bool) {
+        if beeping != self.playing {
+            if beeping {
+                self.device.resume();
+            } else {
+                self.device.pause();
+            }
+            self.playing = beeping;
+        }
+    }
+}
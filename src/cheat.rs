@@ -0,0 +1,353 @@
+// Cheats: searching memory across frames for the bytes behind a lives
+// counter or a score, and freezing memory or registers to fixed values
+// every frame. Cheat lists are saved per ROM in a file named after the
+// SHA-1 of the ROM, one freeze per line, all numbers in hex:
+//
+//     # Infinite lives
+//     2F0 = 03
+//     V5 = 0A
+use crate::chip8::{Chip8, MEM_SIZE, REG_SIZE};
+use crate::romdb;
+use core::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    Memory(usize),
+    Register(usize),
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    // Parses "2F0", "0x2F0" or "V5"
+    fn from_str(s: &str) -> Result<Target, String> {
+        let error = || format!("Invalid address or register: {}", s);
+
+        // One hex digit, V0 to VF
+        if let Some(reg) = s.strip_prefix(['V', 'v']) {
+            return usize::from_str_radix(reg, 16)
+                .ok()
+                .filter(|reg| *reg < REG_SIZE)
+                .map(Target::Register)
+                .ok_or_else(error);
+        }
+
+        parse_hex(s)
+            .filter(|addr| *addr < MEM_SIZE)
+            .map(Target::Memory)
+            .ok_or_else(error)
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Memory(addr) => write!(f, "{:03X}", addr),
+            Target::Register(reg) => write!(f, "V{:X}", reg),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Freeze {
+    pub target: Target,
+    pub value: u8,
+}
+
+impl FromStr for Freeze {
+    type Err = String;
+
+    // Parses "2F0 = 03"
+    fn from_str(s: &str) -> Result<Freeze, String> {
+        let (target, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Missing value: {}", s))?;
+
+        Ok(Freeze {
+            target: target.trim().parse()?,
+            value: parse_byte(value.trim())?,
+        })
+    }
+}
+
+impl fmt::Display for Freeze {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} = {:02X}", self.target, self.value)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cheats {
+    freezes: Vec<Freeze>,
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats::default()
+    }
+
+    // File of the ROM with the given SHA-1 in dir
+    pub fn path(dir: &Path, sha1: &[u8; 20]) -> PathBuf {
+        dir.join(format!("{}.cht", romdb::to_hex(sha1)))
+    }
+
+    // Errors name the line, e.g. "3: Missing value: 2F0"
+    pub fn parse(text: &str) -> Result<Cheats, String> {
+        let mut cheats = Cheats::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let freeze = line
+                .parse()
+                .map_err(|e| format!("{}: {}", line_number + 1, e))?;
+            cheats.freeze(freeze);
+        }
+
+        Ok(cheats)
+    }
+
+    // Errors name the file and line
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Cheats, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+
+        Cheats::parse(&text).map_err(|e| format!("{}:{}", path.display(), e))
+    }
+
+    // Creates the directory of the file if needed
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let error = |e: std::io::Error| format!("Can't write {}: {}", path.display(), e);
+
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(error)?;
+        }
+        fs::write(path, self.to_string()).map_err(error)
+    }
+
+    pub fn freezes(&self) -> &[Freeze] {
+        &self.freezes
+    }
+
+    // Replaces the freeze of the same target
+    pub fn freeze(&mut self, freeze: Freeze) {
+        match self.freezes.iter_mut().find(|f| f.target == freeze.target) {
+            Some(f) => *f = freeze,
+            None => self.freezes.push(freeze),
+        }
+    }
+
+    // Returns whether the target was frozen
+    pub fn unfreeze(&mut self, target: Target) -> bool {
+        let len = self.freezes.len();
+        self.freezes.retain(|f| f.target != target);
+        self.freezes.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.freezes.clear();
+    }
+
+    // Writes the frozen values, called once per frame
+    pub fn apply(&self, chip8: &mut Chip8) {
+        for freeze in self.freezes.iter() {
+            match freeze.target {
+                Target::Memory(addr) => chip8.poke(addr, freeze.value),
+                Target::Register(reg) => chip8.set_v(reg, freeze.value),
+            }
+        }
+    }
+}
+
+impl fmt::Display for Cheats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for freeze in self.freezes.iter() {
+            writeln!(f, "{}", freeze)?;
+        }
+
+        Ok(())
+    }
+}
+
+// How a byte compares to its value at the previous search
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Comparison {
+    fn matches(self, previous: u8, value: u8) -> bool {
+        match self {
+            Comparison::Equal(v) => value == v,
+            Comparison::Changed => value != previous,
+            Comparison::Unchanged => value == previous,
+            Comparison::Increased => value > previous,
+            Comparison::Decreased => value < previous,
+        }
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = String;
+
+    // Parses "changed", "unchanged", "increased", "decreased" or a value
+    fn from_str(s: &str) -> Result<Comparison, String> {
+        match s {
+            "changed" => Ok(Comparison::Changed),
+            "unchanged" => Ok(Comparison::Unchanged),
+            "increased" => Ok(Comparison::Increased),
+            "decreased" => Ok(Comparison::Decreased),
+            _ => parse_byte(s).map(Comparison::Equal),
+        }
+    }
+}
+
+// Narrows down the addresses matching each comparison in turn, e.g. equal
+// to the number of lives, then decreased after losing one
+pub struct Search {
+    // Sorted addresses still matching
+    candidates: Vec<usize>,
+
+    // Memory at the last comparison
+    previous: [u8; MEM_SIZE],
+}
+
+impl Search {
+    // Starts with every address
+    pub fn new(chip8: &Chip8) -> Search {
+        let mut previous = [0; MEM_SIZE];
+        previous.copy_from_slice(chip8.memory());
+
+        Search {
+            candidates: (0..MEM_SIZE).collect(),
+            previous,
+        }
+    }
+
+    // Keeps the addresses matching, returns how many are left
+    pub fn filter(&mut self, chip8: &Chip8, comparison: Comparison) -> usize {
+        let memory = chip8.memory();
+        let previous = &self.previous;
+        self.candidates
+            .retain(|addr| comparison.matches(previous[*addr], memory[*addr]));
+        self.previous.copy_from_slice(memory);
+
+        self.candidates.len()
+    }
+
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+}
+
+// Hex with an optional 0x
+fn parse_hex(s: &str) -> Option<usize> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+
+    usize::from_str_radix(digits, 16).ok()
+}
+
+fn parse_byte(s: &str) -> Result<u8, String> {
+    parse_hex(s)
+        .filter(|value| *value <= 0xFF)
+        .map(|value| value as u8)
+        .ok_or_else(|| format!("Invalid byte: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::PC_START;
+
+    #[test]
+    fn test_parse() {
+        let cheats = Cheats::parse("# Lives\n2F0 = 03\nvA=ff\n0x2F0 = 4\n").unwrap();
+
+        assert_eq!(
+            cheats.freezes(),
+            [
+                Freeze {
+                    target: Target::Memory(0x2F0),
+                    value: 4,
+                },
+                Freeze {
+                    target: Target::Register(0xA),
+                    value: 0xFF,
+                },
+            ]
+        );
+        assert_eq!(cheats.to_string(), "2F0 = 04\nVA = FF\n");
+        assert_eq!(Cheats::parse(&cheats.to_string()), Ok(cheats));
+
+        assert_eq!(Cheats::parse("\n2F0").unwrap_err(), "2: Missing value: 2F0");
+        assert!(Cheats::parse("1000 = 1").is_err());
+        assert!(Cheats::parse("V10 = 1").is_err());
+        assert!(Cheats::parse("2F0 = 100").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut chip8 = Chip8::init();
+        let mut cheats = Cheats::new();
+        cheats.freeze("300 = 7".parse().unwrap());
+        cheats.freeze("V2 = 9".parse().unwrap());
+
+        cheats.apply(&mut chip8);
+        assert_eq!(chip8.peek(0x300), 7);
+        assert_eq!(chip8.v()[2], 9);
+
+        assert!(cheats.unfreeze(Target::Memory(0x300)));
+        assert!(!cheats.unfreeze(Target::Memory(0x300)));
+        assert_eq!(cheats.freezes().len(), 1);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut chip8 = Chip8::init();
+        chip8.poke(0x300, 3);
+        chip8.poke(0x301, 3);
+        let mut search = Search::new(&chip8);
+
+        assert_eq!(search.filter(&chip8, Comparison::Equal(3)), 2);
+
+        // Lose a life
+        chip8.poke(0x300, 2);
+        assert_eq!(search.filter(&chip8, "decreased".parse().unwrap()), 1);
+        assert_eq!(search.candidates(), [0x300]);
+
+        assert_eq!(search.filter(&chip8, Comparison::Unchanged), 1);
+        assert_eq!(search.filter(&chip8, Comparison::Changed), 0);
+        assert!("bigger".parse::<Comparison>().is_err());
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = std::env::temp_dir().join("chip8_rust_test_cheats");
+        let _ = fs::remove_dir_all(&dir);
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(&[0x12, 0x00]).unwrap();
+        let path = Cheats::path(&dir, chip8.rom_sha1());
+
+        let mut cheats = Cheats::new();
+        cheats.freeze(format!("{:X} = 1", PC_START).parse().unwrap());
+        cheats.save(&path).unwrap();
+
+        assert_eq!(Cheats::load(&path), Ok(cheats));
+        assert!(path.to_string_lossy().ends_with(".cht"));
+    }
+}
@@ -1,7 +1,19 @@
-use rand::Rng;
+#[cfg(feature = "std")]
+use crate::patch;
+use crate::platform::Quirks;
+use crate::rng::XorShift;
+#[cfg(feature = "std")]
+use crate::romdb::RomDb;
+use crate::sha1::sha1;
+use core::fmt;
+#[cfg(feature = "std")]
 use std::error::Error;
-use std::fs::File;
+#[cfg(feature = "std")]
+use std::fs::{self, File};
+#[cfg(feature = "std")]
 use std::io::Read;
+#[cfg(feature = "std")]
+use std::path::Path;
 
 // Constant definitions
 const FONT_SET: [u8; 80] = [
@@ -22,22 +34,179 @@ const FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
-const MEM_SIZE: usize = 4096;
-const STACK_SIZE: usize = 24;
-const REG_SIZE: usize = 16;
+pub const MEM_SIZE: usize = 4096;
+// Deepest stack supported, stack_depth of the quirks can be lower
+pub const STACK_SIZE: usize = 24;
+
+// Stack location with the stack_in_memory quirk, the VIP kept it right
+// below the display memory. Return addresses are big endian.
+pub const STACK_ADDR: usize = 0xEA0;
+
+// Display memory with the vip_memory_map quirk, one bit per pixel,
+// row by row, most significant bit first
+pub const DISPLAY_ADDR: usize = 0xF00;
+
+// Font with the vip_memory_map quirk, at the end of the interpreter area
+// below PC_START. The VIP read the digits from its monitor ROM, which is
+// outside of the 4 KiB emulated here.
+pub const VIP_FONT_ADDR: usize = PC_START - FONT_SET.len();
+pub const REG_SIZE: usize = 16;
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
-const KEY_SIZE: usize = 16;
-const PC_START: usize = 0x200;
+pub const KEY_SIZE: usize = 16;
+pub const PC_START: usize = 0x200;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadError {
+    // ROM doesn't fit in memory after PC_START
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::RomTooLarge { size, max } => write!(
+                f,
+                "ROM is too large: {} bytes, at most {} bytes fit",
+                size, max
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for LoadError {}
+
+// Save state format, bump STATE_VERSION whenever the layout changes
+const STATE_MAGIC: [u8; 4] = *b"C8ST";
+const STATE_VERSION: u8 = 2;
+pub const STATE_SIZE: usize = STATE_MAGIC.len()
+    + 1 // Version
+    + 2 // opcode
+    + MEM_SIZE
+    + REG_SIZE
+    + 2 // addr_reg
+    + 2 // pc
+    + STACK_SIZE * 2
+    + 1 // sp
+    + SCREEN_WIDTH * SCREEN_HEIGHT
+    + 1 // draw_flag
+    + KEY_SIZE
+    + 1 // key_to_wait_reg
+    + 1 // delay_timer
+    + 1 // sound_timer
+    + 1 // quirks
+    + 1 // stack_depth
+    + 1 // fault
+    + 8; // rng
+
+// Errors that halt the machine, the faulting instruction isn't executed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    // 2NNN with stack_depth return addresses on the stack
+    StackOverflow,
+
+    // 00EE with an empty stack
+    StackUnderflow,
+}
+
+impl Fault {
+    fn to_u8(self) -> u8 {
+        match self {
+            Fault::StackOverflow => 1,
+            Fault::StackUnderflow => 2,
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::StackOverflow => write!(f, "Stack overflow"),
+            Fault::StackUnderflow => write!(f, "Stack underflow"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for Fault {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StateError {
+    // Buffer is smaller than STATE_SIZE
+    BufferTooSmall,
+
+    // Not a save state
+    BadMagic,
+
+    UnsupportedVersion(u8),
+
+    // I, pc or return addresses point outside of memory, or sp outside of
+    // the stack
+    Corrupted,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BufferTooSmall => write!(f, "Save state is truncated"),
+            StateError::BadMagic => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion(v) => write!(f, "Unsupported save state version {}", v),
+            StateError::Corrupted => write!(f, "Save state is corrupted"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for StateError {}
+
+// A sprite drawn by DXYN, for debugging graphics
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Draw {
+    // Address of the instruction
+    pub pc: usize,
+
+    // Top left corner, after wrapping around the screen
+    pub x: usize,
+    pub y: usize,
+
+    pub height: usize,
+
+    // Sprite address, I when drawing
+    pub addr: usize,
+
+    // Whether a pixel was turned off, what vF is set to
+    pub collision: bool,
+}
+
+impl fmt::Display for Draw {
+    // "0x204: 8x5 sprite from 0x2A0 at (12, 8), collision"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "0x{:03X}: 8x{} sprite from 0x{:03X} at ({}, {}), {}",
+            self.pc,
+            self.height,
+            self.addr,
+            self.x,
+            self.y,
+            if self.collision {
+                "collision"
+            } else {
+                "no collision"
+            }
+        )
+    }
+}
 
 pub struct Chip8 {
     // Current opcode
-    pub opcode: usize,
+    opcode: usize,
 
     memory: [u8; MEM_SIZE],
 
     // CPU Register v0 - vF, vF is used as flag
-    pub v: [u8; REG_SIZE],
+    v: [u8; REG_SIZE],
 
     // Address Register
     addr_reg: usize,
@@ -45,22 +214,24 @@ pub struct Chip8 {
     // Program Counter starts at 0x200
     pc: usize,
 
-    // Only used to store Return Address (16 bit)
+    // Only used to store Return Address (16 bit).
+    // Unused with the stack_in_memory quirk.
     stack: [usize; STACK_SIZE],
 
     // Stack pointer
     sp: usize,
 
     // Screen 64 x 32 pixels, monochrome
-    pub screen: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    screen: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
 
     // Flag for drawing screen
-    pub draw_flag: bool,
+    draw_flag: bool,
 
     // Array to store current of hex keyboard (0x0 - 0xF)
-    pub key: [bool; KEY_SIZE],
+    key: [bool; KEY_SIZE],
 
-    pub key_to_wait_reg: Option<usize>,
+    // Register waiting for a key press (FX0A)
+    key_to_wait_reg: Option<usize>,
 
     // Timer at 60 Hz, count down to 0 from current value
     delay_timer: u8,
@@ -68,6 +239,72 @@ pub struct Chip8 {
     // Timer at 60 Hz, count down to 0 from current value.
     // Beeping sound is made when it reaches 0.
     sound_timer: u8,
+
+    // Interpreter behaviours that differ between platforms
+    quirks: Quirks,
+
+    // Set when the machine halts on an error
+    fault: Option<Fault>,
+
+    // Timers are ticked by tick_timers() only, not after every instruction
+    timers_per_frame: bool,
+
+    // SHA-1 of the loaded ROM, identifies it in the ROM database
+    rom_sha1: [u8; 20],
+
+    // Last DXYN executed, not part of save states
+    last_draw: Option<Draw>,
+
+    // Copy of the loaded ROM for hard_reset(), not part of save states
+    rom: [u8; MEM_SIZE - PC_START],
+    rom_len: usize,
+
+    // Source of CXNN random numbers
+    rng: XorShift,
+}
+
+// Sequential writes into a save state buffer
+struct StateWriter<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> StateWriter<'a> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    fn u16(&mut self, value: usize) {
+        self.bytes(&(value as u16).to_le_bytes());
+    }
+}
+
+// Sequential reads from a save state buffer, the length is checked up front
+struct StateReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn u16(&mut self) -> usize {
+        let bytes = self.bytes(2);
+        u16::from_le_bytes([bytes[0], bytes[1]]) as usize
+    }
 }
 
 impl Chip8 {
@@ -96,25 +333,500 @@ impl Chip8 {
             // Initialize input
             key: [false; KEY_SIZE],
             key_to_wait_reg: None,
+
+            quirks: Quirks::DEFAULT,
+            fault: None,
+            timers_per_frame: false,
+            rom_sha1: [0; 20],
+            last_draw: None,
+            rom: [0; MEM_SIZE - PC_START],
+            rom_len: 0,
+            rng: XorShift::new(),
         };
 
-        // Load fontset
-        for (i, byte) in FONT_SET.iter().enumerate() {
-            emu.memory[i] = *byte;
-        }
+        emu.load_font();
 
         emu
     }
 
+    // Loads a ROM file with the quirks the bundled ROM database gives it.
+    // A patch next to it, see patch::find(), is applied first.
+    #[cfg(feature = "std")]
     pub fn load_game(&mut self, filename: &str) -> Result<usize, Box<dyn Error>> {
+        let patch = patch::find(Path::new(filename));
+        self.load_game_with(filename, patch.as_deref(), RomDb::bundled())
+    }
+
+    // Loads a ROM file after applying the IPS or BPS patch file if any.
+    // If the database knows the patched ROM its quirks are applied first,
+    // so the ROM must fit in their memory map.
+    #[cfg(feature = "std")]
+    pub fn load_game_with(
+        &mut self,
+        filename: &str,
+        patch: Option<&Path>,
+        db: &RomDb,
+    ) -> Result<usize, Box<dyn Error>> {
         let mut f = File::open(filename)?;
         let mut buffer = Vec::<u8>::new();
-        let buf_size = f.read_to_end(&mut buffer)?;
+        f.read_to_end(&mut buffer)?;
+
+        if let Some(path) = patch {
+            let patch =
+                fs::read(path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+            buffer = patch::apply(&buffer, &patch)
+                .map_err(|e| format!("Can't apply {}: {}", path.display(), e))?;
+        }
+
+        if let Some(quirks) = db.lookup(&buffer).and_then(|i| i.platform_quirks()) {
+            self.set_quirks(quirks);
+        }
+
+        Ok(self.load_rom(&buffer)?)
+    }
+
+    // Copies a ROM image into memory at PC_START.
+    // With the vip_memory_map quirk it must end before the stack.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<usize, LoadError> {
+        let end = if self.quirks.vip_memory_map {
+            STACK_ADDR
+        } else {
+            MEM_SIZE
+        };
+
+        if rom.len() > end - PC_START {
+            return Err(LoadError::RomTooLarge {
+                size: rom.len(),
+                max: end - PC_START,
+            });
+        }
+
+        self.memory[PC_START..(rom.len() + PC_START)].clone_from_slice(rom);
+        self.rom_sha1 = sha1(rom);
+        self.rom[..rom.len()].copy_from_slice(rom);
+        self.rom_len = rom.len();
+
+        Ok(rom.len())
+    }
+
+    // Loads the last ROM loaded again, e.g. after set_quirks() changed the
+    // memory map. Fails if it no longer fits.
+    pub fn reload_rom(&mut self) -> Result<usize, LoadError> {
+        let rom = self.rom;
+        self.load_rom(&rom[..self.rom_len])
+    }
+
+    // Power-on state of the CPU, screen and timers. Memory is left alone,
+    // so the ROM and anything it wrote stay. Quirks, the random number
+    // generator and held keys are kept too.
+    pub fn reset(&mut self) {
+        self.opcode = 0;
+        self.v = [0; REG_SIZE];
+        self.addr_reg = 0;
+        self.pc = PC_START;
+        self.stack = [0; STACK_SIZE];
+        self.sp = 0;
+        self.screen = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        self.draw_flag = true;
+        self.key_to_wait_reg = None;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.fault = None;
+        self.last_draw = None;
+
+        if self.quirks.vip_memory_map {
+            self.store_display();
+        }
+    }
+
+    // reset() after clearing memory and loading the font and the last ROM
+    // loaded again
+    pub fn hard_reset(&mut self) {
+        self.memory = [0; MEM_SIZE];
+        self.load_font();
+        self.memory[PC_START..PC_START + self.rom_len].copy_from_slice(&self.rom[..self.rom_len]);
+
+        self.reset();
+    }
+
+    // SHA-1 of the last ROM loaded, zeros before
+    pub fn rom_sha1(&self) -> &[u8; 20] {
+        &self.rom_sha1
+    }
+
+    // Panics if stack_depth is above STACK_SIZE
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        assert!(
+            quirks.stack_depth <= STACK_SIZE,
+            "Stack depth out of range: {}",
+            quirks.stack_depth
+        );
+        let moves_font = quirks.vip_memory_map != self.quirks.vip_memory_map;
+        self.quirks = quirks;
+
+        if moves_font {
+            self.load_font();
+        }
+        if quirks.vip_memory_map {
+            self.store_display();
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // Makes CXNN deterministic
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = XorShift::from_seed(seed);
+    }
+
+    // Executes one instruction, unless waiting for a key press or halted
+    // by a fault. Returns whether an instruction was executed.
+    pub fn step(&mut self) -> bool {
+        if self.key_to_wait_reg.is_some() || self.fault.is_some() {
+            return false;
+        }
+
+        self.emulate();
+        self.fault.is_none()
+    }
+
+    // Executes up to the given number of instructions, stopping early
+    // when waiting for a key press. Returns the number executed.
+    //
+    // A call is one 60 Hz frame, the timers tick once at its end whether
+    // or not they are ticked per frame.
+    pub fn run_frame(&mut self, instructions: u32) -> u32 {
+        let timers_per_frame = self.timers_per_frame;
+        self.timers_per_frame = true;
+
+        let mut executed = 0;
+        while executed < instructions && self.step() {
+            executed += 1;
+        }
+
+        self.timers_per_frame = timers_per_frame;
+        self.tick_timers();
+
+        executed
+    }
+
+    // Presses or releases key 0x0 - 0xF, a press resumes FX0A
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        self.key[key] = pressed;
+
+        if pressed {
+            if let Some(x) = self.key_to_wait_reg {
+                self.v[x] = key as u8;
+                self.key_to_wait_reg = None;
+            }
+        }
+    }
+
+    pub fn is_key_pressed(&self, key: usize) -> bool {
+        self.key[key]
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_to_wait_reg.is_some()
+    }
+
+    // Error that halted the machine, pc is left on the faulting instruction
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    // Framebuffer, one byte per pixel (0 or 1), row by row
+    pub fn screen(&self) -> &[u8] {
+        &self.screen
+    }
+
+    // Whether the screen changed since the last clear_draw_flag()
+    pub fn draw_flag(&self) -> bool {
+        self.draw_flag
+    }
+
+    pub fn clear_draw_flag(&mut self) {
+        self.draw_flag = false;
+    }
+
+    // Reads memory, wrapping around at the end of the address space
+    pub fn peek(&self, addr: usize) -> u8 {
+        self.memory[addr % MEM_SIZE]
+    }
+
+    // Writes memory, wrapping around at the end of the address space
+    pub fn poke(&mut self, addr: usize, value: u8) {
+        self.memory[addr % MEM_SIZE] = value;
+
+        if self.quirks.vip_memory_map && addr % MEM_SIZE >= DISPLAY_ADDR {
+            self.load_display();
+        }
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // The screen isn't updated from display memory with vip_memory_map,
+    // use poke() for that
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    // Last executed opcode
+    pub fn opcode(&self) -> usize {
+        self.opcode
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn v(&self) -> &[u8; REG_SIZE] {
+        &self.v
+    }
+
+    pub fn set_v(&mut self, reg: usize, value: u8) {
+        self.v[reg] = value;
+    }
+
+    // Address register I
+    pub fn i(&self) -> usize {
+        self.addr_reg
+    }
+
+    pub fn set_i(&mut self, addr: usize) {
+        self.addr_reg = addr;
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    // Panics if sp is above the stack depth
+    pub fn set_sp(&mut self, sp: usize) {
+        assert!(
+            sp <= self.quirks.stack_depth,
+            "Stack pointer out of range: {}",
+            sp
+        );
+        self.sp = sp;
+    }
+
+    // Return addresses currently on the stack, oldest first
+    pub fn stack(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.sp).map(move |level| self.stack_entry(level))
+    }
+
+    fn stack_entry(&self, level: usize) -> usize {
+        if self.has_stack_in_memory() {
+            let addr = STACK_ADDR + level * 2;
+            (self.memory[addr] as usize) << 8 | self.memory[addr + 1] as usize
+        } else {
+            self.stack[level]
+        }
+    }
+
+    pub fn last_draw(&self) -> Option<Draw> {
+        self.last_draw
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
 
-        // excluding EOF
-        self.memory[PC_START..(buf_size + PC_START)].clone_from_slice(&buffer[..buf_size]);
+    // Writes the machine state into buf, which must hold STATE_SIZE bytes.
+    // The ROM is part of memory, so it's restored too.
+    pub fn save_state(&self, buf: &mut [u8]) -> Result<usize, StateError> {
+        if buf.len() < STATE_SIZE {
+            return Err(StateError::BufferTooSmall);
+        }
+
+        let mut w = StateWriter { buf, pos: 0 };
+        w.bytes(&STATE_MAGIC);
+        w.u8(STATE_VERSION);
+        w.u16(self.opcode);
+        w.bytes(&self.memory);
+        w.bytes(&self.v);
+        w.u16(self.addr_reg);
+        w.u16(self.pc);
+        for addr in self.stack.iter() {
+            w.u16(*addr);
+        }
+        w.u8(self.sp as u8);
+        w.bytes(&self.screen);
+        w.u8(self.draw_flag as u8);
+        for pressed in self.key.iter() {
+            w.u8(*pressed as u8);
+        }
+        w.u8(self.key_to_wait_reg.map_or(0xFF, |x| x as u8));
+        w.u8(self.delay_timer);
+        w.u8(self.sound_timer);
+        w.u8(self.quirks.to_bits());
+        w.u8(self.quirks.stack_depth as u8);
+        w.u8(self.fault.map_or(0, Fault::to_u8));
+        w.bytes(&self.rng.state().to_le_bytes());
+
+        Ok(w.pos)
+    }
+
+    // Restores a state written by save_state(), the machine is left
+    // untouched if it fails
+    pub fn load_state(&mut self, buf: &[u8]) -> Result<(), StateError> {
+        if buf.len() < STATE_SIZE {
+            return Err(StateError::BufferTooSmall);
+        }
+
+        let mut r = StateReader { buf, pos: 0 };
+        if r.bytes(STATE_MAGIC.len()) != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.u8();
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let opcode = r.u16();
+        let memory = r.bytes(MEM_SIZE);
+        let v = r.bytes(REG_SIZE);
+        let addr_reg = r.u16();
+        let pc = r.u16();
+        let mut stack = [0; STACK_SIZE];
+        for addr in stack.iter_mut() {
+            *addr = r.u16();
+        }
+        let sp = r.u8() as usize;
+        let screen = r.bytes(SCREEN_WIDTH * SCREEN_HEIGHT);
+        let draw_flag = r.u8() != 0;
+        let key = r.bytes(KEY_SIZE);
+        let key_to_wait_reg = match r.u8() {
+            0xFF => None,
+            x => Some(x as usize),
+        };
+        let delay_timer = r.u8();
+        let sound_timer = r.u8();
+        let quirk_bits = r.u8();
+        let stack_depth = r.u8() as usize;
+        let quirks = Quirks::from_bits(quirk_bits, stack_depth);
+        let fault = match r.u8() {
+            0 => None,
+            1 => Some(Fault::StackOverflow),
+            2 => Some(Fault::StackUnderflow),
+            _ => return Err(StateError::Corrupted),
+        };
+        let rng = r.bytes(8);
+
+        // Room for the 2 bytes of the opcode at pc, and at the return
+        // addresses 00EE loads into it
+        let valid_pc = |pc: usize| pc <= MEM_SIZE - 2;
+        let return_addr = |level: usize| {
+            if quirks.stack_in_memory || quirks.vip_memory_map {
+                let addr = STACK_ADDR + level * 2;
+                (memory[addr] as usize) << 8 | memory[addr + 1] as usize
+            } else {
+                stack[level]
+            }
+        };
+
+        if !valid_pc(pc)
+            || addr_reg >= MEM_SIZE
+            || stack_depth > STACK_SIZE
+            || sp > stack_depth
+            || !(0..sp).all(|level| valid_pc(return_addr(level)))
+            || key_to_wait_reg.is_some_and(|x| x >= REG_SIZE)
+        {
+            return Err(StateError::Corrupted);
+        }
+
+        self.opcode = opcode;
+        self.memory.copy_from_slice(memory);
+        self.v.copy_from_slice(v);
+        self.addr_reg = addr_reg;
+        self.pc = pc;
+        self.stack = stack;
+        self.sp = sp;
+        self.screen.copy_from_slice(screen);
+        self.draw_flag = draw_flag;
+        for (pressed, byte) in self.key.iter_mut().zip(key) {
+            *pressed = *byte != 0;
+        }
+        self.key_to_wait_reg = key_to_wait_reg;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.quirks = quirks;
+        self.fault = fault;
+        let mut seed = [0; 8];
+        seed.copy_from_slice(rng);
+        self.rng = XorShift::from_seed(u64::from_le_bytes(seed));
+
+        Ok(())
+    }
 
-        Ok(buf_size) // excluding EOF
+    // Address of the font, where FX29 points
+    fn font_addr(&self) -> usize {
+        if self.quirks.vip_memory_map {
+            VIP_FONT_ADDR
+        } else {
+            0
+        }
+    }
+
+    // Clears the interpreter area below PC_START and loads the font in it
+    fn load_font(&mut self) {
+        let addr = self.font_addr();
+        self.memory[..PC_START].fill(0);
+        self.memory[addr..addr + FONT_SET.len()].copy_from_slice(&FONT_SET);
+    }
+
+    // Memory write by an instruction. With vip_memory_map the interpreter
+    // area below PC_START is reserved, writes to it are dropped.
+    fn store(&mut self, addr: usize, value: u8) {
+        if !(self.quirks.vip_memory_map && addr < PC_START) {
+            self.memory[addr] = value;
+        }
+    }
+
+    fn has_stack_in_memory(&self) -> bool {
+        self.quirks.stack_in_memory || self.quirks.vip_memory_map
+    }
+
+    // Packs the framebuffer into display memory
+    fn store_display(&mut self) {
+        for (i, pixels) in self.screen.chunks(8).enumerate() {
+            let byte = pixels
+                .iter()
+                .fold(0, |byte, pixel| byte << 1 | (*pixel & 1));
+            self.memory[DISPLAY_ADDR + i] = byte;
+        }
+    }
+
+    // Unpacks display memory into the framebuffer, after the ROM wrote to it
+    fn load_display(&mut self) {
+        for (i, pixels) in self.screen.chunks_mut(8).enumerate() {
+            let byte = self.memory[DISPLAY_ADDR + i];
+            for (bit, pixel) in pixels.iter_mut().enumerate() {
+                let value = (byte >> (7 - bit)) & 1;
+                if *pixel != value {
+                    *pixel = value;
+                    self.draw_flag = true;
+                }
+            }
+        }
+    }
+
+    // Whether the beep is sounding, i.e. sound_timer hasn't reached 0
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
     }
 
     pub fn emulate(&mut self) {
@@ -142,7 +854,33 @@ impl Chip8 {
             _ => panic!("Unknown opcode: 0x{:04X}!", self.opcode), // Unreachable
         };
 
-        // Update timers
+        // The machine halts on faults, time stops too
+        if self.fault.is_some() {
+            return;
+        }
+
+        // Keep display memory and the framebuffer in sync
+        if self.quirks.vip_memory_map {
+            if self.opcode == 0x00E0 || self.opcode & 0xF000 == 0xD000 {
+                self.store_display();
+            } else if matches!(self.opcode & 0xF0FF, 0xF033 | 0xF055) {
+                self.load_display();
+            }
+        }
+
+        if !self.timers_per_frame {
+            self.tick_timers();
+        }
+    }
+
+    // Counts the timers down by one, emulate() does it after every
+    // instruction unless timers are ticked per frame, run_frame() once
+    pub fn tick_timers(&mut self) {
+        // Time stops with the machine on faults
+        if self.fault.is_some() {
+            return;
+        }
+
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         };
@@ -155,6 +893,12 @@ impl Chip8 {
         };
     }
 
+    // Stops emulate() from ticking the timers, the frontend calls
+    // tick_timers() at 60 Hz instead
+    pub fn set_timers_per_frame(&mut self, enabled: bool) {
+        self.timers_per_frame = enabled;
+    }
+
     fn opcode_0(&mut self) {
         match self.opcode {
             0x00E0 => {
@@ -165,8 +909,12 @@ impl Chip8 {
             }
             0x00EE => {
                 // Returns from a subroutine
+                if self.sp == 0 {
+                    self.fault = Some(Fault::StackUnderflow);
+                    return;
+                }
                 self.sp -= 1;
-                self.pc = self.stack[self.sp];
+                self.pc = self.stack_entry(self.sp);
             }
             _ => panic!("Unknown opcode: 0x{:04X}!", self.opcode),
         };
@@ -181,7 +929,19 @@ impl Chip8 {
     fn opcode_2(&mut self) {
         // Opcode: 2NNN
         // Calls subroutine at 0x0NNN
-        self.stack[self.sp] = self.pc + 2;
+        if self.sp >= self.quirks.stack_depth {
+            self.fault = Some(Fault::StackOverflow);
+            return;
+        }
+
+        let addr = self.pc + 2;
+        if self.has_stack_in_memory() {
+            let entry = STACK_ADDR + self.sp * 2;
+            self.memory[entry] = (addr >> 8) as u8;
+            self.memory[entry + 1] = addr as u8;
+        } else {
+            self.stack[self.sp] = addr;
+        }
         self.sp += 1;
         self.pc = self.opcode & 0x0FFF;
     }
@@ -252,14 +1012,23 @@ impl Chip8 {
             }
             0x0001 => {
                 self.v[x] |= self.v[y];
+                if self.quirks.vf_reset {
+                    self.v[0x0F] = 0;
+                }
                 self.pc += 2;
             }
             0x0002 => {
                 self.v[x] &= self.v[y];
+                if self.quirks.vf_reset {
+                    self.v[0x0F] = 0;
+                }
                 self.pc += 2;
             }
             0x0003 => {
                 self.v[x] ^= self.v[y];
+                if self.quirks.vf_reset {
+                    self.v[0x0F] = 0;
+                }
                 self.pc += 2;
             }
             0x0004 => {
@@ -292,9 +1061,14 @@ impl Chip8 {
             }
             0x0006 => {
                 // Store lsb of vX in vF
-                // vX >>= 1
-                self.v[0x0F] = self.v[x] & 0x01;
-                self.v[x] >>= 1;
+                // vX >>= 1, or vX = vY >> 1 with shift_uses_vy quirk
+                let value = if self.quirks.shift_uses_vy {
+                    self.v[y]
+                } else {
+                    self.v[x]
+                };
+                self.v[x] = value >> 1;
+                self.v[0x0F] = value & 0x01;
                 self.pc += 2;
             }
             0x0007 => {
@@ -313,9 +1087,14 @@ impl Chip8 {
             }
             0x000E => {
                 // Store msb of vX in vF
-                // vX <<= 1
-                self.v[0x0F] = (self.v[x] & 0x80) >> 7;
-                self.v[x] <<= 1;
+                // vX <<= 1, or vX = vY << 1 with shift_uses_vy quirk
+                let value = if self.quirks.shift_uses_vy {
+                    self.v[y]
+                } else {
+                    self.v[x]
+                };
+                self.v[x] = value << 1;
+                self.v[0x0F] = (value & 0x80) >> 7;
                 self.pc += 2;
             }
             _ => panic!("Unknown opcode: 0x{:04X}!", self.opcode),
@@ -344,14 +1123,19 @@ impl Chip8 {
 
     fn opcode_b(&mut self) {
         // Opcode: BNNN
-        // Go to address v0 + NNN
-        self.pc = self.v[0] as usize + (self.opcode & 0x0FFF);
+        // Go to address v0 + NNN, or vX + XNN with jump_uses_vx quirk
+        let x = if self.quirks.jump_uses_vx {
+            (self.opcode & 0x0F00) >> 8
+        } else {
+            0
+        };
+        self.pc = self.v[x] as usize + (self.opcode & 0x0FFF);
     }
 
     fn opcode_c(&mut self) {
         // Opcode: CXNN
         // vX = rand(0 to 255) & NN
-        let secret = rand::thread_rng().gen_range(0, 256) as u8;
+        let secret = self.rng.next_u8();
         let x = (self.opcode & 0x0F00) >> 8;
         let nn = (self.opcode & 0x00FF) as u8;
 
@@ -365,8 +1149,10 @@ impl Chip8 {
         // Each row of 8 pixels is read as bit-coded from memory at addr_reg
         // addr_reg doesn't change after this instruction
         // Sets vF to 1 if any screen pixels are flipped from set to unset, set to 0, otherwise
-        let x = self.v[(self.opcode & 0x0F00) >> 8] as usize;
-        let y = self.v[(self.opcode & 0x00F0) >> 4] as usize;
+        // The starting position always wraps, the sprite itself is clipped
+        // with clip_sprites quirk
+        let x = self.v[(self.opcode & 0x0F00) >> 8] as usize % SCREEN_WIDTH;
+        let y = self.v[(self.opcode & 0x00F0) >> 4] as usize % SCREEN_HEIGHT;
         let h = self.opcode & 0x000F; // Do not add 1 because for loop start from 0
         let mut sprite;
 
@@ -375,7 +1161,13 @@ impl Chip8 {
         for y_row in 0..h {
             sprite = self.memory[y_row + self.addr_reg];
             // println!("sprite[{}]: {:02X}", y_row, sprite); // Debug
+            if self.quirks.clip_sprites && y + y_row >= SCREEN_HEIGHT {
+                break;
+            }
             for x_col in 0..8 {
+                if self.quirks.clip_sprites && x + x_col >= SCREEN_WIDTH {
+                    break;
+                }
                 if sprite & (0x80 >> x_col) != 0 {
                     let coordinate = ((x + x_col) % SCREEN_WIDTH)
                         + (((y + y_row) % SCREEN_HEIGHT) * SCREEN_WIDTH);
@@ -389,6 +1181,14 @@ impl Chip8 {
             }
         }
 
+        self.last_draw = Some(Draw {
+            pc: self.pc,
+            x,
+            y,
+            height: h,
+            addr: self.addr_reg,
+            collision: self.v[0x0F] == 1,
+        });
         self.pc += 2;
         self.draw_flag = true;
     }
@@ -450,7 +1250,7 @@ impl Chip8 {
                     panic!("Font set is only for character 0 to F!");
                 };
 
-                self.addr_reg = (self.v[x] * 5) as usize;
+                self.addr_reg = self.font_addr() + self.v[x] as usize * 5;
                 self.pc += 2;
             }
             0x0033 => {
@@ -458,26 +1258,32 @@ impl Chip8 {
                 // Stores the hundreds digit at memory[addr_reg]
                 // Stores the tens digit at memory[addr_reg + 1]
                 // Stores the ones digit at memory[addr_reg + 2]
-                self.memory[self.addr_reg] = self.v[x] / 100;
-                self.memory[self.addr_reg + 1] = (self.v[x] / 10) % 10;
-                self.memory[self.addr_reg + 2] = (self.v[x] % 100) % 10;
+                self.store(self.addr_reg, self.v[x] / 100);
+                self.store(self.addr_reg + 1, (self.v[x] / 10) % 10);
+                self.store(self.addr_reg + 2, (self.v[x] % 100) % 10);
 
                 self.pc += 2;
             }
             0x0055 => {
                 // Stores v[0 to X] in memory starting at addr_reg
-                // addr_reg is not modified
+                // addr_reg is not modified, unless load_store_increments_i quirk
                 for i in 0..x + 1 {
-                    self.memory[self.addr_reg + i] = self.v[i];
+                    self.store(self.addr_reg + i, self.v[i]);
+                }
+                if self.quirks.load_store_increments_i {
+                    self.addr_reg += x + 1;
                 }
                 self.pc += 2;
             }
             0x0065 => {
                 // Fills v[0 to X] by value in memory starting addr_reg
-                // addr_reg is not modified
+                // addr_reg is not modified, unless load_store_increments_i quirk
                 for i in 0..x + 1 {
                     self.v[i] = self.memory[self.addr_reg + i];
                 }
+                if self.quirks.load_store_increments_i {
+                    self.addr_reg += x + 1;
+                }
                 self.pc += 2;
             }
             _ => panic!("Unknown opcode: 0x{:04X}!", self.opcode),
@@ -499,6 +1305,13 @@ mod tests {
         }
     }
 
+    fn store_opcode_at(emu: &mut Chip8, addr: usize, opcode: &[u16]) {
+        for (i, op) in opcode.iter().enumerate() {
+            emu.memory[addr + i * 2] = (op >> 8) as u8;
+            emu.memory[addr + i * 2 + 1] = (op & 0x00FF) as u8;
+        }
+    }
+
     #[test]
     fn test_init() {
         let emu = Chip8::init();
@@ -515,7 +1328,7 @@ mod tests {
             [0_u8; MEM_SIZE - FONT_SET.len()]
         ); // memory is 8 bit wide
         assert_eq!(emu.screen, [0_u8; SCREEN_WIDTH * SCREEN_HEIGHT]); // screen use u8
-        assert_eq!(emu.draw_flag, false);
+        assert!(!emu.draw_flag);
         assert_eq!(emu.delay_timer, 0);
         assert_eq!(emu.sound_timer, 0);
         assert_eq!(emu.key, [false; KEY_SIZE]);
@@ -523,6 +1336,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "std")]
     fn test_load_game() {
         let mut emu = Chip8::init();
         let size = emu.load_game("IBM Logo.ch8").unwrap();
@@ -530,6 +1344,251 @@ mod tests {
         assert_eq!(size, 132);
     }
 
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_load_game_rom_db() {
+        let rom = [0x12, 0x00];
+        let path = std::env::temp_dir().join("chip8_rust_test_rom_db.ch8");
+        std::fs::write(&path, rom).unwrap();
+        let hash = crate::romdb::to_hex(&sha1(&rom));
+        let db = RomDb::parse(&format!("[{}]\nplatform = schip\n", hash)).unwrap();
+
+        let mut emu = Chip8::init();
+        emu.load_game_with(path.to_str().unwrap(), None, &db)
+            .unwrap();
+        assert_eq!(emu.rom_sha1(), &sha1(&rom));
+        assert_eq!(emu.quirks(), Quirks::SCHIP);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_load_game_patch() {
+        let dir = std::env::temp_dir().join("chip8_rust_test_patch");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.ch8");
+        std::fs::write(&rom, [0x00, 0xE0, 0x12, 0x00]).unwrap();
+        // IPS replacing the jump target
+        std::fs::write(dir.join("game.ips"), b"PATCH\x00\x00\x03\x00\x01\x02EOF").unwrap();
+
+        let mut emu = Chip8::init();
+        assert_eq!(emu.load_game(rom.to_str().unwrap()).unwrap(), 4);
+        assert_eq!(emu.memory[PC_START..PC_START + 4], [0x00, 0xE0, 0x12, 0x02]);
+
+        std::fs::write(dir.join("game.ips"), b"PATCH\x00").unwrap();
+        let error = emu.load_game(rom.to_str().unwrap()).unwrap_err();
+        assert!(error.to_string().ends_with("game.ips: Patch is truncated"));
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut emu = Chip8::init();
+        emu.load_rom(&[0x60, 0x05, 0xA3, 0x00, 0xF0, 0x55, 0x00, 0xE0])
+            .unwrap();
+        emu.run_frame(3);
+        emu.delay_timer = 10;
+        emu.screen[0] = 1;
+
+        emu.reset();
+        assert_eq!(emu.pc, PC_START);
+        assert_eq!(emu.v, [0; REG_SIZE]);
+        assert_eq!(emu.addr_reg, 0);
+        assert_eq!(emu.delay_timer, 0);
+        assert_eq!(emu.screen[0], 0);
+        assert!(emu.draw_flag);
+        // Memory written by the ROM stays
+        assert_eq!(emu.memory[0x300], 5);
+
+        emu.hard_reset();
+        assert_eq!(emu.memory[0x300], 0);
+        assert_eq!(emu.memory[PC_START..PC_START + 2], [0x60, 0x05]);
+        assert_eq!(emu.memory[..FONT_SET.len()], FONT_SET);
+    }
+
+    #[test]
+    fn test_load_rom() {
+        let mut emu = Chip8::init();
+        let size = emu.load_rom(&[0x12, 0x34, 0x56]).unwrap();
+
+        assert_eq!(size, 3);
+        assert_eq!(emu.memory[PC_START..PC_START + 3], [0x12, 0x34, 0x56]);
+    }
+
+    #[test]
+    fn test_load_rom_too_large() {
+        let mut emu = Chip8::init();
+
+        assert!(emu.load_rom(&[0; MEM_SIZE - PC_START]).is_ok());
+        assert_eq!(
+            emu.load_rom(&[0; MEM_SIZE - PC_START + 1]),
+            Err(LoadError::RomTooLarge {
+                size: MEM_SIZE - PC_START + 1,
+                max: MEM_SIZE - PC_START
+            })
+        );
+    }
+
+    #[test]
+    fn test_peek_poke() {
+        let mut emu = Chip8::init();
+
+        emu.poke(0x345, 0xAB);
+        assert_eq!(emu.peek(0x345), 0xAB);
+
+        // Wraps around
+        emu.poke(MEM_SIZE + 1, 0xCD);
+        assert_eq!(emu.peek(1), 0xCD);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut emu = Chip8::init();
+
+        store_opcode(&mut emu, &[0x6001, 0x6102, 0xF20A, 0x6303]);
+
+        // Stops at FX0A
+        assert_eq!(emu.run_frame(10), 3);
+        assert!(emu.is_waiting_for_key());
+        assert!(!emu.step());
+
+        emu.set_key(0xB, true);
+        assert!(!emu.is_waiting_for_key());
+        assert!(emu.is_key_pressed(0xB));
+        assert_eq!(emu.v()[2], 0xB);
+
+        assert_eq!(emu.run_frame(1), 1);
+        assert_eq!(emu.v()[3], 3);
+    }
+
+    #[test]
+    fn test_run_frame_timers() {
+        let mut emu = Chip8::init();
+
+        // Sets DT to 10, then jumps to itself
+        store_opcode(&mut emu, &[0x600A, 0xF015, 0x1204]);
+        assert_eq!(emu.run_frame(2), 2);
+        assert_eq!(emu.delay_timer, 9);
+
+        // Ticks once per frame, however many instructions run
+        emu.delay_timer = 10;
+        assert_eq!(emu.run_frame(12), 12);
+        assert_eq!(emu.delay_timer, 9);
+
+        // Also while waiting for a key
+        emu.pc = PC_START;
+        store_opcode(&mut emu, &[0xF10A, 0x1202]);
+        assert_eq!(emu.run_frame(12), 1);
+        assert_eq!(emu.run_frame(12), 0);
+        assert_eq!(emu.delay_timer, 7);
+
+        // Stepping alone still ticks per instruction
+        emu.set_key(0x1, true);
+        emu.step();
+        assert_eq!(emu.delay_timer, 6);
+
+        // Time stops on faults
+        emu.pc = PC_START;
+        store_opcode(&mut emu, &[0x00EE]);
+        assert_eq!(emu.run_frame(12), 0);
+        assert!(emu.fault().is_some());
+        emu.tick_timers();
+        assert_eq!(emu.delay_timer, 6);
+    }
+
+    #[test]
+    fn test_save_load_state() {
+        let mut emu = Chip8::init();
+        emu.set_quirks(Quirks::VIP);
+        emu.set_seed(3);
+
+        // Calls 0x206, which sets addr_reg, delay_timer and v0
+        store_opcode(&mut emu, &[0x6A12, 0x2206, 0x0000, 0xA456, 0xF015, 0xC0FF]);
+        emu.run_frame(5);
+        emu.set_key(0x7, true);
+
+        let mut state = [0; STATE_SIZE];
+        assert_eq!(emu.save_state(&mut state), Ok(STATE_SIZE));
+
+        let mut restored = Chip8::init();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.memory[..], emu.memory[..]);
+        assert_eq!(restored.v, emu.v);
+        assert_eq!(restored.pc, 0x20C);
+        assert!(restored.stack().eq([PC_START + 4]));
+        assert_eq!(restored.addr_reg, 0x456);
+        assert_eq!(restored.delay_timer, emu.delay_timer);
+        assert_eq!(restored.quirks, Quirks::VIP);
+        assert!(restored.is_key_pressed(0x7));
+
+        // Random numbers continue the same sequence
+        store_opcode(&mut emu, &[0xC0FF]);
+        emu.pc = PC_START;
+        restored.memory[PC_START..PC_START + 2].copy_from_slice(&[0xC0, 0xFF]);
+        restored.pc = PC_START;
+        emu.emulate();
+        restored.emulate();
+        assert_eq!(restored.v[0], emu.v[0]);
+    }
+
+    #[test]
+    fn test_load_state_errors() {
+        let mut emu = Chip8::init();
+        let mut state = [0; STATE_SIZE];
+        emu.save_state(&mut state).unwrap();
+
+        assert_eq!(
+            emu.load_state(&state[..STATE_SIZE - 1]),
+            Err(StateError::BufferTooSmall)
+        );
+
+        let mut bad = state;
+        bad[0] = b'X';
+        assert_eq!(emu.load_state(&bad), Err(StateError::BadMagic));
+
+        let mut bad = state;
+        bad[STATE_MAGIC.len()] = STATE_VERSION + 1;
+        assert_eq!(
+            emu.load_state(&bad),
+            Err(StateError::UnsupportedVersion(STATE_VERSION + 1))
+        );
+
+        let corrupted = |emu: &Chip8| {
+            let mut state = [0; STATE_SIZE];
+            emu.save_state(&mut state).unwrap();
+            Chip8::init().load_state(&state)
+        };
+
+        let mut bad = Chip8::init();
+        bad.pc = MEM_SIZE - 1;
+        assert_eq!(corrupted(&bad), Err(StateError::Corrupted));
+
+        let mut bad = Chip8::init();
+        bad.addr_reg = 0xFFFF;
+        assert_eq!(corrupted(&bad), Err(StateError::Corrupted));
+        bad.addr_reg = MEM_SIZE;
+        assert_eq!(corrupted(&bad), Err(StateError::Corrupted));
+        bad.addr_reg = MEM_SIZE - 1;
+        assert_eq!(corrupted(&bad), Ok(()));
+
+        let mut bad = Chip8::init();
+        bad.stack[..2].copy_from_slice(&[PC_START, MEM_SIZE - 1]);
+        bad.sp = 2;
+        assert_eq!(corrupted(&bad), Err(StateError::Corrupted));
+
+        // Only entries below sp matter
+        bad.sp = 1;
+        assert_eq!(corrupted(&bad), Ok(()));
+
+        let mut bad = Chip8::init();
+        bad.set_quirks(Quirks {
+            stack_in_memory: true,
+            ..Quirks::VIP
+        });
+        bad.memory[STACK_ADDR..STACK_ADDR + 2].copy_from_slice(&[0xFF, 0xFF]);
+        bad.sp = 1;
+        assert_eq!(corrupted(&bad), Err(StateError::Corrupted));
+    }
+
     #[test]
     fn test_opcode_0_clear_screen() {
         let mut emu = Chip8::init();
@@ -597,6 +1656,138 @@ mod tests {
         assert_eq!(emu.stack[emu.sp - 1], PC_START + 2);
     }
 
+    #[test]
+    fn test_opcode_2_stack_overflow() {
+        let mut emu = Chip8::init();
+        emu.set_quirks(Quirks::VIP);
+
+        // Calls itself
+        store_opcode(&mut emu, &[0x2200]);
+
+        assert_eq!(emu.run_frame(20), 12);
+        assert_eq!(emu.fault(), Some(Fault::StackOverflow));
+        assert_eq!(emu.sp, 12);
+        assert_eq!(emu.pc, PC_START);
+
+        // Halted for good
+        assert!(!emu.step());
+        assert_eq!(emu.sp, 12);
+    }
+
+    #[test]
+    fn test_opcode_0_stack_underflow() {
+        let mut emu = Chip8::init();
+        emu.delay_timer = 5;
+
+        store_opcode(&mut emu, &[0x00EE]);
+
+        assert!(!emu.step());
+        assert_eq!(emu.fault(), Some(Fault::StackUnderflow));
+        assert_eq!(emu.pc, PC_START);
+        assert_eq!(emu.delay_timer, 5);
+    }
+
+    #[test]
+    fn test_vip_memory_map() {
+        let mut emu = Chip8::init();
+        let quirks = Quirks {
+            vip_memory_map: true,
+            ..Quirks::VIP
+        };
+        emu.set_quirks(quirks);
+        emu.set_i(VIP_FONT_ADDR);
+
+        // Draws the font of 0 at (8, 0), then calls 0x20A, which stores
+        // v0 - v1 into the display memory at row 1
+        store_opcode(
+            &mut emu,
+            &[0x6008, 0xD015, 0x220A, 0x0000, 0x0000, 0x60FF, 0x6181],
+        );
+        store_opcode_at(&mut emu, 0x20E, &[0xAF08, 0xF155, 0x00EE]);
+        emu.run_frame(2);
+        assert_eq!(emu.memory[DISPLAY_ADDR..DISPLAY_ADDR + 2], [0x00, 0xF0]);
+        assert_eq!(emu.memory[DISPLAY_ADDR + 8 + 1], 0x90);
+
+        emu.run_frame(6);
+        assert_eq!(emu.memory[STACK_ADDR..STACK_ADDR + 2], [0x02, 0x06]);
+        assert_eq!(
+            emu.screen[SCREEN_WIDTH..SCREEN_WIDTH + 9],
+            [1, 1, 1, 1, 1, 1, 1, 1, 1]
+        );
+        assert_eq!(emu.screen[SCREEN_WIDTH + 9..SCREEN_WIDTH + 15], [0; 6]);
+        assert_eq!(emu.screen[SCREEN_WIDTH + 15], 1);
+
+        // Frontends can poke the display too
+        emu.poke(0xFFF, 0x01);
+        assert_eq!(emu.screen[SCREEN_WIDTH * SCREEN_HEIGHT - 1], 1);
+
+        // ROMs must not overlap the stack
+        let mut emu = Chip8::init();
+        emu.set_quirks(quirks);
+        let too_large = Err(LoadError::RomTooLarge {
+            size: 0xCA1,
+            max: 0xCA0,
+        });
+        assert_eq!(emu.load_rom(&[0; 0xCA1]), too_large);
+
+        // Even when loaded before the quirks are set
+        let mut emu = Chip8::init();
+        emu.load_rom(&[0; 0xCA1]).unwrap();
+        emu.set_quirks(quirks);
+        assert_eq!(emu.reload_rom(), too_large);
+    }
+
+    #[test]
+    fn test_vip_interpreter_area() {
+        let mut emu = Chip8::init();
+        emu.set_quirks(Quirks {
+            vip_memory_map: true,
+            ..Quirks::VIP
+        });
+        assert_eq!(emu.memory[..VIP_FONT_ADDR], [0; VIP_FONT_ADDR]);
+        assert_eq!(emu.memory[VIP_FONT_ADDR..PC_START], FONT_SET);
+
+        // I to the font of A, then v0 - v1 stored over it and at PC_START
+        emu.v[0] = 0xA;
+        emu.v[1] = 0x42;
+        store_opcode(&mut emu, &[0xF029, 0xA1FF, 0xF155]);
+        emu.run_frame(1);
+        assert_eq!(emu.addr_reg, VIP_FONT_ADDR + 0xA * 5);
+        emu.run_frame(2);
+        assert_eq!(emu.memory[0x1FF], FONT_SET[FONT_SET.len() - 1]);
+        assert_eq!(emu.memory[PC_START], 0x42);
+
+        emu.hard_reset();
+        assert_eq!(emu.memory[VIP_FONT_ADDR..PC_START], FONT_SET);
+
+        // Back to the usual map
+        emu.set_quirks(Quirks::VIP);
+        assert_eq!(emu.memory[..FONT_SET.len()], FONT_SET);
+        assert_eq!(emu.memory[FONT_SET.len()..PC_START], [0; PC_START - 80]);
+    }
+
+    #[test]
+    fn test_stack_in_memory() {
+        let mut emu = Chip8::init();
+        emu.set_quirks(Quirks {
+            stack_in_memory: true,
+            ..Quirks::VIP
+        });
+
+        // Calls 0x206, which returns
+        store_opcode(&mut emu, &[0x2206, 0x0000, 0x0000, 0x00EE]);
+        emu.run_frame(1);
+        assert_eq!(emu.memory[STACK_ADDR..STACK_ADDR + 2], [0x02, 0x02]);
+        assert!(emu.stack().eq([PC_START + 2]));
+        assert_eq!(emu.stack, [0; STACK_SIZE]);
+
+        // The ROM overwrites its return address
+        emu.poke(STACK_ADDR + 1, 0x40);
+        emu.run_frame(1);
+        assert_eq!(emu.pc, 0x240);
+        assert_eq!(emu.sp, 0);
+    }
+
     #[test]
     fn test_opcode_3_skip() {
         let mut emu = Chip8::init();
@@ -792,6 +1983,25 @@ mod tests {
         assert_eq!(emu.pc, PC_START + 2);
     }
 
+    #[test]
+    fn test_opcode_8_1_vf_reset() {
+        let mut emu = Chip8::init();
+        emu.set_quirks(Quirks::VIP);
+
+        // Init
+        emu.v[2] = 0xAB;
+        emu.v[7] = 0x14;
+        emu.v[0xF] = 1;
+
+        store_opcode(&mut emu, &[0x8271]);
+
+        emu.emulate();
+
+        assert_eq!(emu.v[2], 0xBF);
+        assert_eq!(emu.v[0xF], 0);
+        assert_eq!(emu.pc, PC_START + 2);
+    }
+
     #[test]
     fn test_opcode_8_4() {
         // vX += vY
@@ -869,6 +2079,25 @@ mod tests {
         assert_eq!(emu.pc, PC_START + 4);
     }
 
+    #[test]
+    fn test_opcode_8_6_shift_vy() {
+        // vX = vY >> 1, lsb of vY stored in vF
+        let mut emu = Chip8::init();
+        emu.set_quirks(Quirks::VIP);
+
+        // Init
+        emu.v[2] = 0xFF;
+        emu.v[3] = 0x05;
+
+        store_opcode(&mut emu, &[0x8236]);
+
+        emu.emulate();
+        assert_eq!(emu.v[2], 0x02);
+        assert_eq!(emu.v[3], 0x05);
+        assert_eq!(emu.v[0xF], 1);
+        assert_eq!(emu.pc, PC_START + 2);
+    }
+
     #[test]
     fn test_opcode_8_7() {
         // vX = vY - vX
@@ -919,6 +2148,24 @@ mod tests {
         assert_eq!(emu.pc, PC_START + 4);
     }
 
+    #[test]
+    fn test_opcode_8_e_shift_vy() {
+        // vX = vY << 1, msb of vY stored in vF
+        let mut emu = Chip8::init();
+        emu.set_quirks(Quirks::VIP);
+
+        // Init
+        emu.v[2] = 0x00;
+        emu.v[3] = 0x81;
+
+        store_opcode(&mut emu, &[0x823E]);
+
+        emu.emulate();
+        assert_eq!(emu.v[2], 0x02);
+        assert_eq!(emu.v[0xF], 1);
+        assert_eq!(emu.pc, PC_START + 2);
+    }
+
     #[test]
     #[should_panic(expected = "Unknown opcode: 0x823A!")]
     fn test_opcode_8_unknown() {
@@ -981,6 +2228,21 @@ mod tests {
         assert_eq!(emu.pc, 0xFF + 0xFFF);
     }
 
+    #[test]
+    fn test_opcode_b_jump_uses_vx() {
+        let mut emu = Chip8::init();
+        emu.set_quirks(Quirks::SCHIP);
+
+        // Init
+        emu.v[0] = 0xFF;
+        emu.v[3] = 0x02;
+
+        store_opcode(&mut emu, &[0xB320]);
+
+        emu.emulate();
+        assert_eq!(emu.pc, 0x322);
+    }
+
     #[test]
     fn test_opcode_c_nn_00() {
         let mut emu = Chip8::init();
@@ -1006,6 +2268,99 @@ mod tests {
         assert_eq!(emu.pc, PC_START + 2);
     }
 
+    #[test]
+    fn test_opcode_c_seed() {
+        let mut emu1 = Chip8::init();
+        let mut emu2 = Chip8::init();
+        emu1.set_seed(42);
+        emu2.set_seed(42);
+
+        let op = [0xC0FF; 8];
+        store_opcode(&mut emu1, &op);
+        store_opcode(&mut emu2, &op);
+
+        for _ in 0..op.len() {
+            emu1.emulate();
+            emu2.emulate();
+            assert_eq!(emu1.v[0], emu2.v[0]);
+        }
+    }
+
+    #[test]
+    fn test_opcode_d_wrap() {
+        let mut emu = Chip8::init();
+
+        // Init, 8x1 sprite at the right edge
+        emu.addr_reg = 0x300;
+        emu.memory[0x300] = 0xFF;
+        emu.v[0] = (SCREEN_WIDTH - 4) as u8;
+        emu.v[1] = 0;
+
+        store_opcode(&mut emu, &[0xD011]);
+
+        emu.emulate();
+        assert_eq!(emu.screen[SCREEN_WIDTH - 1], 1);
+        assert_eq!(emu.screen[0], 1);
+        assert_eq!(emu.screen[3], 1);
+        assert_eq!(emu.v[0xF], 0);
+        assert!(emu.draw_flag);
+    }
+
+    #[test]
+    fn test_last_draw() {
+        let mut emu = Chip8::init();
+        emu.addr_reg = 0x300;
+        emu.memory[0x300] = 0x80;
+        emu.v[0] = (SCREEN_WIDTH + 2) as u8;
+        emu.v[1] = 3;
+
+        store_opcode(&mut emu, &[0xD011, 0xD011]);
+        assert_eq!(emu.last_draw(), None);
+
+        emu.emulate();
+        emu.emulate();
+        let draw = emu.last_draw().unwrap();
+        assert_eq!(
+            draw,
+            Draw {
+                pc: PC_START + 2,
+                x: 2,
+                y: 3,
+                height: 1,
+                addr: 0x300,
+                collision: true,
+            }
+        );
+        assert_eq!(
+            draw.to_string(),
+            "0x202: 8x1 sprite from 0x300 at (2, 3), collision"
+        );
+
+        emu.reset();
+        assert_eq!(emu.last_draw(), None);
+    }
+
+    #[test]
+    fn test_opcode_d_clip() {
+        let mut emu = Chip8::init();
+        emu.set_quirks(Quirks::VIP);
+
+        // Init, 8x2 sprite at the bottom right corner
+        emu.addr_reg = 0x300;
+        emu.memory[0x300] = 0xFF;
+        emu.memory[0x301] = 0xFF;
+        emu.v[0] = (SCREEN_WIDTH - 4) as u8;
+        emu.v[1] = (SCREEN_HEIGHT - 1) as u8;
+
+        store_opcode(&mut emu, &[0xD012]);
+
+        emu.emulate();
+        let last_row = (SCREEN_HEIGHT - 1) * SCREEN_WIDTH;
+        assert_eq!(emu.screen[last_row + SCREEN_WIDTH - 1], 1);
+        assert_eq!(emu.screen[last_row], 0);
+        assert_eq!(emu.screen.iter().filter(|p| **p == 1).count(), 4);
+    }
+
     #[test]
     #[should_panic(expected = "Unknown opcode: 0xE2FF")]
     fn test_opcode_e_unknown() {
@@ -1169,6 +2524,23 @@ mod tests {
         assert_eq!(emu.pc, PC_START + 2);
     }
 
+    #[test]
+    fn test_opcode_f_55_increment_i() {
+        let mut emu = Chip8::init();
+        emu.set_quirks(Quirks::VIP);
+
+        // Init
+        emu.addr_reg = 0x234;
+
+        store_opcode(&mut emu, &[0xF355, 0xF365]);
+
+        emu.emulate();
+        assert_eq!(emu.addr_reg, 0x238);
+
+        emu.emulate();
+        assert_eq!(emu.addr_reg, 0x23C);
+    }
+
     #[test]
     #[should_panic(expected = "Unknown opcode: 0xF777!")]
     fn test_opcode_f_unknown() {
@@ -0,0 +1,537 @@
+use crate::display::Filter;
+use chip8_core::chip8::STACK_SIZE;
+use chip8_core::palette::Palette;
+use chip8_core::platform::{Platform, Quirks};
+use std::convert::TryFrom;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: chip8_rust [OPTIONS] [ROM]
+
+ROM can be a directory to pick a ROM from its .ch8, .sc8 and .xo8 files,
+it's the current directory by default.
+
+Options:
+    --ips <n>             Instructions per second, 1 to 1000000
+                          [default: from --rom-db or 120]
+    --vip-timing          Time instructions like the COSMAC VIP, ignores --ips
+    --scale <n>           Initial window scale, 1 to 100 [default: 10]
+    --integer-scaling     Only scale the screen by whole multiples
+    --palette <palette>   default, amber, green, lcd or RRGGBB,RRGGBB (off, on)
+    --rom-db <file>       Extra ROM database, overriding the settings of the bundled one
+    --patch <file>        IPS or BPS patch to apply to the ROM [default: a .bps or
+                          .ips file next to the ROM with its name]
+    --no-patch            Don't apply a patch found next to the ROM
+    --cheat-dir <dir>     Directory of the cheat files, one per ROM [default: cheats]
+    --filter <filter>     Display filter: none, blend, decay or decay:<0.0-1.0>
+    --platform <name>     chip8, schip or xochip, picks the quirks of its interpreter
+    --quirks <preset>     default, vip, schip or xochip [default: default]
+    --stack-depth <n>     Return addresses the stack holds, 1 to 24
+    --stack-in-memory     Keep the stack in emulated memory like the VIP
+    --vip-memory-map      Font at 0x1B0, stack at 0xEA0 and display at 0xF00
+                          like the VIP, 0x000-0x1FF read-only
+    --seed <n>            Seed of the random number generator
+    --mute                Disable the beep
+    --start-paused        Start paused, press Pause or P to resume
+    --debugger            Print each instruction and wait for Enter
+    --gdb <port>          Wait for GDB to connect on localhost:<port>
+    --trace <file>        Log each executed instruction to a file
+    --trace-range <a-b>   Only trace instructions at addresses a to b (hex)
+    --trace-last <n>      Only keep the last n instructions, written on a crash
+    --draw-log <file>     Log each sprite drawn with its position and collision
+    --profile             Print the hot spots and busiest subroutines at exit
+    --coverage <file>     Save the ROM coverage at exit, as HTML for .html files
+    --headless            Run without a window
+    --frames <n>          Quit after n frames
+    --record <file>       Record to .gif, .y4m or .ppm
+    --record-audio        Also record the beep to a .wav next to the recording
+    --config <file>       Read options from a file, one `option = value` per line
+    -h, --help            Print this help
+
+Keys:
+    1-4, Q-R, A-F, Z-V    CHIP-8 keypad
+    Escape                Go back to the launcher, resetting the machine
+    Up, Down, Enter       Pick a ROM in the launcher
+    P, Pause              Pause or resume
+    N                     Run one frame while paused
+    Tab                   Fast-forward while held
+    [ and ]               Halve or double the speed, 1/8x to 8x
+    - and =               One instruction less or more per frame
+    F1                    Show or hide the FPS and IPS
+    F2                    Show or hide the memory viewer, hex keys edit
+                          the byte at its cursor while paused
+    F3                    Show or hide the sprite viewer, I follows I,
+                          arrows move, - and = change the height, L 16x16
+    F4                    Cheat console, commands: search [value|changed|
+                          unchanged|increased|decreased], freeze <addr|Vx>
+                          [value], unfreeze [addr|Vx], list, save
+    F5                    Reset, keeping memory
+    Shift+F5              Hard reset, reloading the ROM
+    F11                   Start or stop a GIF recording
+    F12                   Save a screenshot
+";
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub rom: PathBuf,
+    // None for the ROM's own from the ROM database
+    pub ips: Option<u32>,
+
+    // Budgets machine cycles per frame instead of ips
+    pub vip_timing: bool,
+
+    pub scale: u32,
+    pub integer_scaling: bool,
+    pub palette: Option<Palette>,
+    pub rom_db: Option<PathBuf>,
+    pub patch: Option<PathBuf>,
+    pub no_patch: bool,
+    pub cheat_dir: PathBuf,
+    pub filter: Option<Filter>,
+    pub platform: Option<Platform>,
+
+    // Overrides the quirks of the platform
+    pub quirks: Option<Quirks>,
+
+    // Override the stack of the quirks
+    pub stack_depth: Option<usize>,
+    pub stack_in_memory: bool,
+
+    pub vip_memory_map: bool,
+
+    pub seed: Option<u64>,
+    pub mute: bool,
+    pub start_paused: bool,
+    pub debugger: bool,
+    pub gdb: Option<u16>,
+    pub trace: Option<PathBuf>,
+    pub trace_range: Option<RangeInclusive<usize>>,
+    pub trace_last: usize,
+    pub draw_log: Option<PathBuf>,
+    pub profile: bool,
+    pub coverage: Option<PathBuf>,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub record: Option<PathBuf>,
+    pub record_audio: bool,
+}
+
+impl Options {
+    // Quirks the ROM needs, e.g. from the ROM database or the platform of
+    // its extension, unless the options pick some
+    pub fn quirks_for(&self, rom_quirks: Option<Quirks>) -> Quirks {
+        let mut quirks = self
+            .quirks
+            .or_else(|| self.platform.map(Platform::quirks))
+            .or(rom_quirks)
+            .unwrap_or_default();
+
+        if let Some(depth) = self.stack_depth {
+            quirks.stack_depth = depth;
+        }
+        quirks.stack_in_memory |= self.stack_in_memory;
+        quirks.vip_memory_map |= self.vip_memory_map;
+
+        quirks
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Box<Options>),
+    Help,
+}
+
+// Largest --scale, the window is 64 x 32 times this
+const MAX_SCALE: u32 = 100;
+
+// Largest --ips, about 16667 instructions per frame
+pub const MAX_IPS: u32 = 1_000_000;
+
+// Options without a value
+const SWITCHES: [&str; 12] = [
+    "vip-timing",
+    "integer-scaling",
+    "no-patch",
+    "stack-in-memory",
+    "vip-memory-map",
+    "mute",
+    "start-paused",
+    "debugger",
+    "profile",
+    "headless",
+    "record-audio",
+    "help",
+];
+
+// Parses the command line, excluding the program name.
+// Options from --config are applied first, so the command line overrides them.
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let mut settings = Vec::new();
+    let mut rom = None;
+    let mut config = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let name = match arg.as_str() {
+            "-h" => "help",
+            a if a.starts_with("--") => &a[2..],
+            a if a.starts_with('-') => return Err(format!("Unknown option: {}", a)),
+            a => {
+                if rom.is_some() {
+                    return Err(format!("Unexpected argument: {}", a));
+                }
+                rom = Some(PathBuf::from(a));
+                continue;
+            }
+        };
+
+        if name == "help" {
+            return Ok(Command::Help);
+        }
+
+        // Accept both --option value and --option=value
+        let (name, value) = match name.find('=') {
+            Some(i) => (&name[..i], Some(name[i + 1..].to_string())),
+            None if SWITCHES.contains(&name) => (name, None),
+            None => {
+                let value = args.next().ok_or(format!("Missing value for --{}", name))?;
+                (name, Some(value.clone()))
+            }
+        };
+
+        if name == "config" {
+            config = value;
+        } else {
+            settings.push((name.to_string(), value));
+        }
+    }
+
+    let rom = rom.unwrap_or_else(|| PathBuf::from("."));
+    let mut options = Options {
+        rom,
+        ips: None,
+        vip_timing: false,
+        scale: 10,
+        integer_scaling: false,
+        palette: None,
+        rom_db: None,
+        patch: None,
+        no_patch: false,
+        cheat_dir: PathBuf::from("cheats"),
+        filter: None,
+        platform: None,
+        quirks: None,
+        stack_depth: None,
+        stack_in_memory: false,
+        vip_memory_map: false,
+        seed: None,
+        mute: false,
+        start_paused: false,
+        debugger: false,
+        gdb: None,
+        trace: None,
+        trace_range: None,
+        trace_last: 0,
+        draw_log: None,
+        profile: false,
+        coverage: None,
+        headless: false,
+        frames: None,
+        record: None,
+        record_audio: false,
+    };
+
+    if let Some(path) = config {
+        let content =
+            fs::read_to_string(&path).map_err(|e| format!("Can't read config {}: {}", path, e))?;
+
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => (line, "true"),
+            };
+            apply(&mut options, name, Some(value))
+                .map_err(|e| format!("{}:{}: {}", path, line_number + 1, e))?;
+        }
+    }
+
+    for (name, value) in settings {
+        apply(&mut options, &name, value.as_deref())?;
+    }
+
+    if options.scale == 0 || options.scale > MAX_SCALE {
+        return Err(format!("--scale must be 1 to {}", MAX_SCALE));
+    }
+    if options.ips.is_some_and(|ips| ips == 0 || ips > MAX_IPS) {
+        return Err(format!("--ips must be 1 to {}", MAX_IPS));
+    }
+    if options
+        .stack_depth
+        .is_some_and(|d| d == 0 || d > STACK_SIZE)
+    {
+        return Err(format!("--stack-depth must be 1 to {}", STACK_SIZE));
+    }
+    if options.record_audio && options.record.is_none() {
+        return Err("--record-audio requires --record".to_string());
+    }
+    if options.debugger && options.gdb.is_some() {
+        return Err("--debugger can't be used with --gdb".to_string());
+    }
+    if (options.trace.is_some() || options.trace_last > 0) && options.gdb.is_some() {
+        return Err("--trace can't be used with --gdb".to_string());
+    }
+    if options.draw_log.is_some() && options.gdb.is_some() {
+        return Err("--draw-log can't be used with --gdb".to_string());
+    }
+    if (options.profile || options.coverage.is_some()) && options.gdb.is_some() {
+        return Err("--profile and --coverage can't be used with --gdb".to_string());
+    }
+    if options.vip_timing && options.gdb.is_some() {
+        return Err("--vip-timing can't be used with --gdb".to_string());
+    }
+
+    Ok(Command::Run(Box::new(options)))
+}
+
+// Sets one option, value is None for switches given on the command line
+fn apply(options: &mut Options, name: &str, value: Option<&str>) -> Result<(), String> {
+    let switch = || match value {
+        None | Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(v) => Err(format!("Invalid value for --{}: {}", name, v)),
+    };
+    let value = || value.ok_or(format!("Missing value for --{}", name));
+    let number = || {
+        let v = value()?;
+        v.parse::<u64>()
+            .map_err(|_| format!("Invalid number for --{}: {}", name, v))
+    };
+    let u32_number = || {
+        let n = number()?;
+        u32::try_from(n).map_err(|_| format!("Number too large for --{}: {}", name, n))
+    };
+
+    match name {
+        "ips" => options.ips = Some(u32_number()?),
+        "vip-timing" => options.vip_timing = switch()?,
+        "scale" => options.scale = u32_number()?,
+        "integer-scaling" => options.integer_scaling = switch()?,
+        "palette" => options.palette = Some(value()?.parse()?),
+        "rom-db" => options.rom_db = Some(PathBuf::from(value()?)),
+        "patch" => options.patch = Some(PathBuf::from(value()?)),
+        "no-patch" => options.no_patch = switch()?,
+        "cheat-dir" => options.cheat_dir = PathBuf::from(value()?),
+        "filter" => options.filter = Some(value()?.parse()?),
+        "platform" => options.platform = Some(value()?.parse()?),
+        "quirks" => options.quirks = Some(value()?.parse()?),
+        "stack-depth" => options.stack_depth = Some(number()? as usize),
+        "stack-in-memory" => options.stack_in_memory = switch()?,
+        "vip-memory-map" => options.vip_memory_map = switch()?,
+        "seed" => options.seed = Some(number()?),
+        "mute" => options.mute = switch()?,
+        "start-paused" => options.start_paused = switch()?,
+        "debugger" => options.debugger = switch()?,
+        "gdb" => {
+            let v = value()?;
+            let port = v
+                .parse::<u16>()
+                .map_err(|_| format!("Invalid port for --gdb: {}", v))?;
+            options.gdb = Some(port);
+        }
+        "trace" => options.trace = Some(PathBuf::from(value()?)),
+        "trace-range" => options.trace_range = Some(parse_range(value()?)?),
+        "trace-last" => options.trace_last = number()? as usize,
+        "draw-log" => options.draw_log = Some(PathBuf::from(value()?)),
+        "profile" => options.profile = switch()?,
+        "coverage" => options.coverage = Some(PathBuf::from(value()?)),
+        "headless" => options.headless = switch()?,
+        "frames" => options.frames = Some(number()?),
+        "record" => options.record = Some(PathBuf::from(value()?)),
+        "record-audio" => options.record_audio = switch()?,
+        _ => return Err(format!("Unknown option: --{}", name)),
+    }
+
+    Ok(())
+}
+
+// Parses an address range like "200-2FF"
+fn parse_range(s: &str) -> Result<RangeInclusive<usize>, String> {
+    let error = || format!("Invalid address range: {}", s);
+    let mut parts = s.splitn(2, '-');
+    let start = parts.next().ok_or_else(error)?;
+    let end = parts.next().ok_or_else(error)?;
+
+    let start = usize::from_str_radix(start.trim_start_matches("0x"), 16).map_err(|_| error())?;
+    let end = usize::from_str_radix(end.trim_start_matches("0x"), 16).map_err(|_| error())?;
+    if start > end {
+        return Err(error());
+    }
+
+    Ok(start..=end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    fn options(s: &str) -> Options {
+        match parse(&args(s)).unwrap() {
+            Command::Run(options) => *options,
+            Command::Help => panic!("Unexpected help"),
+        }
+    }
+
+    #[test]
+    fn test_defaults() {
+        let options = options("game.ch8");
+
+        assert_eq!(options.rom, PathBuf::from("game.ch8"));
+        assert_eq!(options.ips, None);
+        assert!(!options.vip_timing);
+        assert_eq!(options.quirks_for(None), Quirks::DEFAULT);
+        assert!(!options.headless);
+        assert_eq!(options.cheat_dir, PathBuf::from("cheats"));
+        assert_eq!(options.patch, None);
+        assert!(!options.no_patch);
+    }
+
+    #[test]
+    fn test_rom_quirks() {
+        assert_eq!(options("").rom, PathBuf::from("."));
+        assert_eq!(
+            options("--rom-db my.txt game.ch8").rom_db,
+            Some(PathBuf::from("my.txt"))
+        );
+
+        let patched = options("--patch fix.bps --no-patch game.ch8");
+        assert_eq!(patched.patch, Some(PathBuf::from("fix.bps")));
+        assert!(patched.no_patch);
+
+        let schip = Some(Quirks::SCHIP);
+        assert_eq!(options("game.sc8").quirks_for(schip), Quirks::SCHIP);
+        assert_eq!(options("game.ch8").quirks_for(None), Quirks::DEFAULT);
+        assert_eq!(
+            options("--platform xochip game.sc8").quirks_for(schip),
+            Quirks::XOCHIP
+        );
+        assert_eq!(
+            options("--quirks vip game.sc8").quirks_for(schip),
+            Quirks::VIP
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let options = options(
+            "--ips 700 --scale=4 --mute --palette amber --platform schip game.ch8 --seed 7",
+        );
+
+        assert_eq!(options.ips, Some(700));
+        assert_eq!(options.scale, 4);
+        assert!(options.mute);
+        assert_eq!(options.palette, Some(Palette::AMBER));
+        assert_eq!(options.quirks_for(None), Quirks::SCHIP);
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.gdb, None);
+        assert_eq!(
+            self::options("--cheat-dir my_cheats game.ch8").cheat_dir,
+            PathBuf::from("my_cheats")
+        );
+    }
+
+    #[test]
+    fn test_quirks_override_platform() {
+        let options = options("--platform schip --quirks default game.ch8");
+
+        assert_eq!(options.platform, Some(Platform::Schip));
+        assert_eq!(options.quirks_for(None), Quirks::DEFAULT);
+    }
+
+    #[test]
+    fn test_trace() {
+        let options = options("--trace t.log --trace-range 0x200-2ff --trace-last 50 game.ch8");
+
+        assert_eq!(options.trace, Some(PathBuf::from("t.log")));
+        assert_eq!(options.trace_range, Some(0x200..=0x2FF));
+        assert_eq!(options.trace_last, 50);
+        assert_eq!(
+            self::options("--draw-log d.log game.ch8").draw_log,
+            Some(PathBuf::from("d.log"))
+        );
+        assert!(parse(&args("--draw-log d.log --gdb 1234 game.ch8")).is_err());
+        assert!(parse(&args("--trace-range 300-200 game.ch8")).is_err());
+        assert!(parse(&args("--trace-range 300 game.ch8")).is_err());
+    }
+
+    #[test]
+    fn test_stack() {
+        let quirks = options("--platform chip8 --stack-depth 16 --stack-in-memory game.ch8")
+            .quirks_for(None);
+
+        assert_eq!(quirks.stack_depth, 16);
+        assert!(quirks.stack_in_memory);
+        assert!(quirks.vf_reset);
+        assert!(
+            options("--vip-memory-map game.ch8")
+                .quirks_for(None)
+                .vip_memory_map
+        );
+        assert!(parse(&args("--stack-depth 25 game.ch8")).is_err());
+    }
+
+    #[test]
+    fn test_help() {
+        assert_eq!(parse(&args("--ips 5 -h")), Ok(Command::Help));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(parse(&args("--ips fast game.ch8")).is_err());
+        assert!(parse(&args("--bogus game.ch8")).is_err());
+        assert!(parse(&args("game.ch8 --scale")).is_err());
+        assert!(parse(&args("--scale 101 game.ch8")).is_err());
+        assert_eq!(
+            parse(&args("--scale 4294967297 game.ch8")),
+            Err("Number too large for --scale: 4294967297".to_string())
+        );
+        assert!(parse(&args("--ips 4294967296 game.ch8")).is_err());
+        assert_eq!(
+            parse(&args("--ips 4294967295 game.ch8")),
+            Err("--ips must be 1 to 1000000".to_string())
+        );
+        assert!(parse(&args("--ips 0 game.ch8")).is_err());
+        assert!(parse(&args("--ips 1000000 game.ch8")).is_ok());
+        assert!(parse(&args("a.ch8 b.ch8")).is_err());
+        assert!(parse(&args("--record-audio game.ch8")).is_err());
+        assert!(parse(&args("--gdb 99999 game.ch8")).is_err());
+        assert!(parse(&args("--gdb 1234 --debugger game.ch8")).is_err());
+        assert!(parse(&args("--gdb 1234 --profile game.ch8")).is_err());
+        assert!(parse(&args("--gdb 1234 --vip-timing game.ch8")).is_err());
+    }
+
+    #[test]
+    fn test_config_file() {
+        let path = env::temp_dir().join("chip8_rust_test_config.cfg");
+        fs::write(&path, "# Settings\nips = 500\nmute\nscale = 3\n").unwrap();
+
+        let options = options(&format!("--config {} --scale 6 game.ch8", path.display()));
+        assert_eq!(options.ips, Some(500));
+        assert!(options.mute);
+        assert_eq!(options.scale, 6);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+// Cheat console, opened with F4: a command line over the screen to search
+// memory and freeze values. Numbers are hex. Commands:
+//
+//     search               Start a new search with every address
+//     search <value>       Keep the addresses holding value
+//     search changed       Keep the addresses changed since the last search,
+//                          or unchanged, increased, decreased
+//     freeze <target> [v]  Freeze an address or register (V0-VF) to v, or
+//                          to its current value
+//     unfreeze [target]    Unfreeze one target, or all of them
+//     list                 Show the freezes
+//     save                 Save the freezes to the cheat file of the ROM
+use chip8_core::cheat::{Cheats, Comparison, Freeze, Search, Target};
+use chip8_core::Chip8;
+use std::path::Path;
+
+// Addresses listed after a search, the count is always shown
+const SHOWN_CANDIDATES: usize = 6;
+
+pub struct Console {
+    line: String,
+
+    // Started by the first search command
+    search: Option<Search>,
+}
+
+impl Console {
+    pub fn new() -> Console {
+        Console {
+            line: String::new(),
+            search: None,
+        }
+    }
+
+    // Command line as shown, with a cursor
+    pub fn prompt(&self) -> String {
+        format!("> {}_", self.line)
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        self.line.push_str(text);
+    }
+
+    pub fn backspace(&mut self) {
+        self.line.pop();
+    }
+
+    // Runs the typed command, returns the message to show
+    pub fn enter(&mut self, chip8: &mut Chip8, cheats: &mut Cheats, file: &Path) -> String {
+        let line = std::mem::take(&mut self.line);
+        match self.execute(line.trim(), chip8, cheats, file) {
+            Ok(message) => message,
+            Err(e) => e,
+        }
+    }
+
+    fn execute(
+        &mut self,
+        line: &str,
+        chip8: &mut Chip8,
+        cheats: &mut Cheats,
+        file: &Path,
+    ) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+
+        match (command, args.as_slice()) {
+            ("search", []) => {
+                self.search = Some(Search::new(chip8));
+                Ok("New search".to_string())
+            }
+            ("search", [comparison]) => {
+                let comparison: Comparison = comparison.parse()?;
+                let search = self.search.get_or_insert_with(|| Search::new(chip8));
+                search.filter(chip8, comparison);
+                Ok(candidates(search.candidates()))
+            }
+            ("freeze", [target, value @ ..]) if value.len() <= 1 => {
+                let target: Target = target.parse()?;
+                let value = match value.first() {
+                    Some(v) => format!("{} = {}", target, v).parse::<Freeze>()?.value,
+                    None => match target {
+                        Target::Memory(addr) => chip8.peek(addr),
+                        Target::Register(reg) => chip8.v()[reg],
+                    },
+                };
+
+                let freeze = Freeze { target, value };
+                cheats.freeze(freeze);
+                cheats.apply(chip8);
+                Ok(format!("Frozen {}", freeze))
+            }
+            ("unfreeze", []) => {
+                cheats.clear();
+                Ok("Unfrozen all".to_string())
+            }
+            ("unfreeze", [target]) => {
+                let target: Target = target.parse()?;
+                if cheats.unfreeze(target) {
+                    Ok(format!("Unfrozen {}", target))
+                } else {
+                    Err(format!("{} isn't frozen", target))
+                }
+            }
+            ("list", []) if cheats.freezes().is_empty() => Ok("No freezes".to_string()),
+            ("list", []) => Ok(cheats
+                .freezes()
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")),
+            ("save", []) => {
+                cheats.save(file)?;
+                Ok(format!("Saved {}", file.display()))
+            }
+            ("", []) => Ok(String::new()),
+            _ => Err(format!("Unknown command: {}", line)),
+        }
+    }
+}
+
+// "3 matches: 2F0 2F4 301"
+fn candidates(addrs: &[usize]) -> String {
+    let mut text = match addrs.len() {
+        1 => "1 match".to_string(),
+        n => format!("{} matches", n),
+    };
+
+    if !addrs.is_empty() && addrs.len() <= SHOWN_CANDIDATES {
+        text.push(':');
+        for addr in addrs {
+            text.push_str(&format!(" {:03X}", addr));
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(console: &mut Console, chip8: &mut Chip8, cheats: &mut Cheats, line: &str) -> String {
+        console.type_text(line);
+        console.enter(chip8, cheats, Path::new("unused.cht"))
+    }
+
+    #[test]
+    fn test_search() {
+        let mut console = Console::new();
+        let mut chip8 = Chip8::init();
+        let mut cheats = Cheats::new();
+        chip8.poke(0x300, 3);
+
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "search"),
+            "New search"
+        );
+        chip8.poke(0x300, 2);
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "search decreased"),
+            "1 match: 300"
+        );
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "search 5"),
+            "0 matches"
+        );
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "search bigger"),
+            "Invalid byte: bigger"
+        );
+    }
+
+    #[test]
+    fn test_freeze() {
+        let mut console = Console::new();
+        let mut chip8 = Chip8::init();
+        let mut cheats = Cheats::new();
+        chip8.poke(0x300, 3);
+
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "freeze 300"),
+            "Frozen 300 = 03"
+        );
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "freeze v1 0A"),
+            "Frozen V1 = 0A"
+        );
+        assert_eq!(chip8.v()[1], 0xA);
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "list"),
+            "300 = 03, V1 = 0A"
+        );
+
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "unfreeze 300"),
+            "Unfrozen 300"
+        );
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "unfreeze 300"),
+            "300 isn't frozen"
+        );
+        run(&mut console, &mut chip8, &mut cheats, "unfreeze");
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "list"),
+            "No freezes"
+        );
+        assert_eq!(
+            run(&mut console, &mut chip8, &mut cheats, "jump 200"),
+            "Unknown command: jump 200"
+        );
+    }
+
+    #[test]
+    fn test_prompt() {
+        let mut console = Console::new();
+        console.type_text("lisx");
+        console.backspace();
+        console.type_text("t");
+        assert_eq!(console.prompt(), "> list_");
+    }
+}
@@ -0,0 +1,252 @@
+// Coverage of a ROM from a profile: which bytes were executed as code,
+// read as data by DXYN/FX65, or never touched. Exported as an annotated
+// disassembly, or as HTML for .html paths.
+use crate::disasm::Instruction;
+use crate::profile::Profiler;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Class {
+    Code,
+    Data,
+    Untouched,
+}
+
+impl Class {
+    fn name(self) -> &'static str {
+        match self {
+            Class::Code => "code",
+            Class::Data => "data",
+            Class::Untouched => "untouched",
+        }
+    }
+}
+
+// One line of the listing: an executed instruction, or a single byte
+struct Line {
+    addr: usize,
+    len: usize,
+    class: Class,
+    text: String,
+}
+
+// Classifies a byte of memory, a byte is code if an instruction
+// starting on it or on the byte before was executed
+pub fn classify(profiler: &Profiler, addr: usize) -> Class {
+    let executed = profiler.count(addr) > 0 || (addr > 0 && profiler.count(addr - 1) > 0);
+
+    if executed {
+        Class::Code
+    } else if profiler.reads(addr) > 0 {
+        Class::Data
+    } else {
+        Class::Untouched
+    }
+}
+
+fn lines(profiler: &Profiler, memory: &[u8], range: Range<usize>) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut addr = range.start;
+
+    while addr < range.end {
+        let count = profiler.count(addr);
+
+        let line = if count > 0 && addr + 1 < range.end {
+            let opcode = (memory[addr] as usize) << 8 | memory[addr + 1] as usize;
+            let text = match Instruction::decode(opcode) {
+                Some(instruction) => instruction.to_string(),
+                None => "???".to_string(),
+            };
+
+            Line {
+                addr,
+                len: 2,
+                class: Class::Code,
+                text: format!("{:04X}  {:<20} ; x{}", opcode, text, count),
+            }
+        } else {
+            let class = classify(profiler, addr);
+            let text = match class {
+                Class::Data => format!(
+                    "{:02X}    {:<20} ; read x{}",
+                    memory[addr],
+                    "data",
+                    profiler.reads(addr)
+                ),
+                _ => format!("{:02X}    {}", memory[addr], class.name()),
+            };
+
+            Line {
+                addr,
+                len: 1,
+                class,
+                text,
+            }
+        };
+
+        addr += line.len;
+        lines.push(line);
+    }
+
+    lines
+}
+
+// Bytes of each class in the range
+fn summary(profiler: &Profiler, range: Range<usize>) -> String {
+    let mut counts = [0; 3];
+    for addr in range.clone() {
+        counts[classify(profiler, addr) as usize] += 1;
+    }
+
+    format!(
+        "{}/{} bytes executed, {} read as data, {} untouched",
+        counts[Class::Code as usize],
+        range.len(),
+        counts[Class::Data as usize],
+        counts[Class::Untouched as usize]
+    )
+}
+
+// Writes the disassembly of memory[range], executed instructions with
+// their count and other bytes with their class
+pub fn write_listing<W: Write>(
+    mut out: W,
+    profiler: &Profiler,
+    memory: &[u8],
+    range: Range<usize>,
+) -> io::Result<()> {
+    writeln!(out, "; Coverage: {}", summary(profiler, range.clone()))?;
+
+    for line in lines(profiler, memory, range) {
+        writeln!(out, "0x{:04X}  {}", line.addr, line.text.trim_end())?;
+    }
+
+    Ok(())
+}
+
+// Same as write_listing, colored by class
+pub fn write_html<W: Write>(
+    mut out: W,
+    profiler: &Profiler,
+    memory: &[u8],
+    range: Range<usize>,
+) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>CHIP-8 coverage</title>\n<style>")?;
+    writeln!(out, "body {{ background: #111; color: #ccc; }}")?;
+    writeln!(out, ".code {{ color: #6c6; }}")?;
+    writeln!(out, ".data {{ color: #69f; }}")?;
+    writeln!(out, ".untouched {{ color: #666; }}")?;
+    writeln!(out, "</style>\n</head>\n<body>")?;
+    writeln!(out, "<p>{}</p>\n<pre>", summary(profiler, range.clone()))?;
+
+    for line in lines(profiler, memory, range) {
+        writeln!(
+            out,
+            "<span class=\"{}\">0x{:04X}  {}</span>",
+            line.class.name(),
+            line.addr,
+            escape(line.text.trim_end())
+        )?;
+    }
+
+    writeln!(out, "</pre>\n</body>\n</html>")
+}
+
+// Writes HTML for .html and .htm paths, a listing otherwise
+pub fn save<P: AsRef<Path>>(
+    path: P,
+    profiler: &Profiler,
+    memory: &[u8],
+    range: Range<usize>,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let html = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm"));
+
+    let mut out = BufWriter::new(File::create(path)?);
+    if html {
+        write_html(&mut out, profiler, memory, range)?;
+    } else {
+        write_listing(&mut out, profiler, memory, range)?;
+    }
+
+    out.flush()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{Chip8, PC_START};
+
+    // Draws the sprite at 0x206 forever, 0x207 is never touched
+    const PROGRAM: [u8; 8] = [0xA2, 0x06, 0xD0, 0x01, 0x12, 0x02, 0xF0, 0xAA];
+
+    fn profile() -> (Chip8, Profiler) {
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(&PROGRAM).unwrap();
+        let mut profiler = Profiler::new();
+
+        for _ in 0..5 {
+            profiler.record(&chip8);
+            chip8.step();
+        }
+
+        (chip8, profiler)
+    }
+
+    #[test]
+    fn test_classify() {
+        let (_, profiler) = profile();
+
+        assert_eq!(classify(&profiler, 0x200), Class::Code);
+        assert_eq!(classify(&profiler, 0x201), Class::Code);
+        assert_eq!(classify(&profiler, 0x206), Class::Data);
+        assert_eq!(classify(&profiler, 0x207), Class::Untouched);
+    }
+
+    #[test]
+    fn test_listing() {
+        let (chip8, profiler) = profile();
+        let range = PC_START..PC_START + PROGRAM.len();
+
+        let mut out = Vec::new();
+        write_listing(&mut out, &profiler, chip8.memory(), range).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "; Coverage: 6/8 bytes executed, 1 read as data, 1 untouched\n\
+             0x0200  A206  LD I, 0x206          ; x1\n\
+             0x0202  D001  DRW V0, V0, 1        ; x2\n\
+             0x0204  1202  JP 0x202             ; x2\n\
+             0x0206  F0    data                 ; read x2\n\
+             0x0207  AA    untouched\n"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        let (chip8, profiler) = profile();
+        let range = PC_START..PC_START + PROGRAM.len();
+
+        let mut out = Vec::new();
+        write_html(&mut out, &profiler, chip8.memory(), range).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(html.contains("<span class=\"code\">0x0200  A206  LD I, 0x206"));
+        assert!(html.contains("<span class=\"untouched\">0x0207  AA    untouched</span>"));
+        assert!(html.ends_with("</html>\n"));
+    }
+}
@@ -0,0 +1,250 @@
+use core::fmt;
+
+// Decoded CHIP-8 instruction, named after the mnemonics of Cowgod's
+// technical reference. x and y are register numbers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    // 00E0
+    Cls,
+    // 00EE
+    Ret,
+    // 1NNN
+    Jp(usize),
+    // 2NNN
+    Call(usize),
+    // 3XNN
+    SeByte(usize, u8),
+    // 4XNN
+    SneByte(usize, u8),
+    // 5XY0
+    SeReg(usize, usize),
+    // 6XNN
+    LdByte(usize, u8),
+    // 7XNN
+    AddByte(usize, u8),
+    // 8XY0
+    LdReg(usize, usize),
+    // 8XY1
+    Or(usize, usize),
+    // 8XY2
+    And(usize, usize),
+    // 8XY3
+    Xor(usize, usize),
+    // 8XY4
+    AddReg(usize, usize),
+    // 8XY5
+    Sub(usize, usize),
+    // 8XY6
+    Shr(usize, usize),
+    // 8XY7
+    Subn(usize, usize),
+    // 8XYE
+    Shl(usize, usize),
+    // 9XY0
+    SneReg(usize, usize),
+    // ANNN
+    LdI(usize),
+    // BNNN, BXNN with the jump_uses_vx quirk
+    JpV0(usize),
+    // CXNN
+    Rnd(usize, u8),
+    // DXYN
+    Drw(usize, usize, usize),
+    // EX9E
+    Skp(usize),
+    // EXA1
+    Sknp(usize),
+    // FX07
+    LdVxDt(usize),
+    // FX0A
+    LdVxKey(usize),
+    // FX15
+    LdDtVx(usize),
+    // FX18
+    LdStVx(usize),
+    // FX1E
+    AddI(usize),
+    // FX29
+    LdFont(usize),
+    // FX33
+    LdBcd(usize),
+    // FX55
+    Store(usize),
+    // FX65
+    Load(usize),
+}
+
+impl Instruction {
+    // Returns None for opcodes the emulator doesn't execute, e.g. 0NNN
+    pub fn decode(opcode: usize) -> Option<Instruction> {
+        let x = (opcode & 0x0F00) >> 8;
+        let y = (opcode & 0x00F0) >> 4;
+        let n = opcode & 0x000F;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        let instruction = match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x00E0 => Instruction::Cls,
+                0x00EE => Instruction::Ret,
+                _ => return None,
+            },
+            0x1000 => Instruction::Jp(nnn),
+            0x2000 => Instruction::Call(nnn),
+            0x3000 => Instruction::SeByte(x, nn),
+            0x4000 => Instruction::SneByte(x, nn),
+            // The low nibble is ignored, like the emulator does
+            0x5000 => Instruction::SeReg(x, y),
+            0x6000 => Instruction::LdByte(x, nn),
+            0x7000 => Instruction::AddByte(x, nn),
+            0x8000 => match n {
+                0x0 => Instruction::LdReg(x, y),
+                0x1 => Instruction::Or(x, y),
+                0x2 => Instruction::And(x, y),
+                0x3 => Instruction::Xor(x, y),
+                0x4 => Instruction::AddReg(x, y),
+                0x5 => Instruction::Sub(x, y),
+                0x6 => Instruction::Shr(x, y),
+                0x7 => Instruction::Subn(x, y),
+                0xE => Instruction::Shl(x, y),
+                _ => return None,
+            },
+            0x9000 => Instruction::SneReg(x, y),
+            0xA000 => Instruction::LdI(nnn),
+            0xB000 => Instruction::JpV0(nnn),
+            0xC000 => Instruction::Rnd(x, nn),
+            0xD000 => Instruction::Drw(x, y, n),
+            0xE000 => match nn {
+                0x9E => Instruction::Skp(x),
+                0xA1 => Instruction::Sknp(x),
+                _ => return None,
+            },
+            _ => match nn {
+                0x07 => Instruction::LdVxDt(x),
+                0x0A => Instruction::LdVxKey(x),
+                0x15 => Instruction::LdDtVx(x),
+                0x18 => Instruction::LdStVx(x),
+                0x1E => Instruction::AddI(x),
+                0x29 => Instruction::LdFont(x),
+                0x33 => Instruction::LdBcd(x),
+                0x55 => Instruction::Store(x),
+                0x65 => Instruction::Load(x),
+                _ => return None,
+            },
+        };
+
+        Some(instruction)
+    }
+
+    // Mnemonic without operands, e.g. to group instructions by type
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Instruction::Cls => "CLS",
+            Instruction::Ret => "RET",
+            Instruction::Jp(_) | Instruction::JpV0(_) => "JP",
+            Instruction::Call(_) => "CALL",
+            Instruction::SeByte(..) | Instruction::SeReg(..) => "SE",
+            Instruction::SneByte(..) | Instruction::SneReg(..) => "SNE",
+            Instruction::LdByte(..)
+            | Instruction::LdReg(..)
+            | Instruction::LdI(_)
+            | Instruction::LdVxDt(_)
+            | Instruction::LdVxKey(_)
+            | Instruction::LdDtVx(_)
+            | Instruction::LdStVx(_)
+            | Instruction::LdFont(_)
+            | Instruction::LdBcd(_)
+            | Instruction::Store(_)
+            | Instruction::Load(_) => "LD",
+            Instruction::AddByte(..) | Instruction::AddReg(..) | Instruction::AddI(_) => "ADD",
+            Instruction::Or(..) => "OR",
+            Instruction::And(..) => "AND",
+            Instruction::Xor(..) => "XOR",
+            Instruction::Sub(..) => "SUB",
+            Instruction::Shr(..) => "SHR",
+            Instruction::Subn(..) => "SUBN",
+            Instruction::Shl(..) => "SHL",
+            Instruction::Rnd(..) => "RND",
+            Instruction::Drw(..) => "DRW",
+            Instruction::Skp(_) => "SKP",
+            Instruction::Sknp(_) => "SKNP",
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let m = self.mnemonic();
+
+        match *self {
+            Instruction::Cls | Instruction::Ret => write!(f, "{}", m),
+            Instruction::Jp(nnn) | Instruction::Call(nnn) => write!(f, "{} 0x{:03X}", m, nnn),
+            Instruction::SeByte(x, nn)
+            | Instruction::SneByte(x, nn)
+            | Instruction::LdByte(x, nn)
+            | Instruction::AddByte(x, nn)
+            | Instruction::Rnd(x, nn) => write!(f, "{} V{:X}, 0x{:02X}", m, x, nn),
+            Instruction::SeReg(x, y)
+            | Instruction::LdReg(x, y)
+            | Instruction::Or(x, y)
+            | Instruction::And(x, y)
+            | Instruction::Xor(x, y)
+            | Instruction::AddReg(x, y)
+            | Instruction::Sub(x, y)
+            | Instruction::Shr(x, y)
+            | Instruction::Subn(x, y)
+            | Instruction::Shl(x, y)
+            | Instruction::SneReg(x, y) => write!(f, "{} V{:X}, V{:X}", m, x, y),
+            Instruction::LdI(nnn) => write!(f, "{} I, 0x{:03X}", m, nnn),
+            Instruction::JpV0(nnn) => write!(f, "{} V0, 0x{:03X}", m, nnn),
+            Instruction::Drw(x, y, n) => write!(f, "{} V{:X}, V{:X}, {}", m, x, y, n),
+            Instruction::Skp(x) | Instruction::Sknp(x) => write!(f, "{} V{:X}", m, x),
+            Instruction::LdVxDt(x) => write!(f, "{} V{:X}, DT", m, x),
+            Instruction::LdVxKey(x) => write!(f, "{} V{:X}, K", m, x),
+            Instruction::LdDtVx(x) => write!(f, "{} DT, V{:X}", m, x),
+            Instruction::LdStVx(x) => write!(f, "{} ST, V{:X}", m, x),
+            Instruction::AddI(x) => write!(f, "{} I, V{:X}", m, x),
+            Instruction::LdFont(x) => write!(f, "{} F, V{:X}", m, x),
+            Instruction::LdBcd(x) => write!(f, "{} B, V{:X}", m, x),
+            Instruction::Store(x) => write!(f, "{} [I], V{:X}", m, x),
+            Instruction::Load(x) => write!(f, "{} V{:X}, [I]", m, x),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Instruction::decode(0x00E0), Some(Instruction::Cls));
+        assert_eq!(Instruction::decode(0x2ABC), Some(Instruction::Call(0xABC)));
+        assert_eq!(
+            Instruction::decode(0x8AB6),
+            Some(Instruction::Shr(0xA, 0xB))
+        );
+        assert_eq!(Instruction::decode(0xD125), Some(Instruction::Drw(1, 2, 5)));
+        assert_eq!(Instruction::decode(0xF365), Some(Instruction::Load(3)));
+
+        assert_eq!(Instruction::decode(0x0123), None);
+        assert_eq!(Instruction::decode(0x8008), None);
+        assert_eq!(Instruction::decode(0xE000), None);
+        assert_eq!(Instruction::decode(0xF0FF), None);
+    }
+
+    #[test]
+    fn test_display() {
+        let text = |opcode| Instruction::decode(opcode).unwrap().to_string();
+
+        assert_eq!(text(0x00EE), "RET");
+        assert_eq!(text(0x1228), "JP 0x228");
+        assert_eq!(text(0x6A42), "LD VA, 0x42");
+        assert_eq!(text(0x8124), "ADD V1, V2");
+        assert_eq!(text(0xA22A), "LD I, 0x22A");
+        assert_eq!(text(0xD01F), "DRW V0, V1, 15");
+        assert_eq!(text(0xF50A), "LD V5, K");
+        assert_eq!(text(0xF255), "LD [I], V2");
+    }
+}
@@ -0,0 +1,382 @@
+use crate::overlay::{self, Overlay, GLYPH_HEIGHT};
+use chip8_core::palette::Palette;
+use chip8_core::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+use std::str::FromStr;
+use std::time::Instant;
+
+// Bytes per pixel of the streaming texture (RGB24)
+const BYTES_PER_PIXEL: usize = 3;
+const PITCH: usize = SCREEN_WIDTH * BYTES_PER_PIXEL;
+
+// Overlay font pixels are this fraction of the screen height, at least 1
+const OVERLAY_LINES: u32 = 160;
+
+// Opacity of the backdrop behind overlay text
+const OVERLAY_ALPHA: u8 = 192;
+
+// Display filter emulating CRT phosphor persistence to hide DXYN flicker
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    // Show the framebuffer as is
+    None,
+
+    // A pixel is lit if it's set in the current or the previous frame
+    Blend,
+
+    // Unset pixels fade out, keeping this fraction of brightness every frame
+    Decay(f32),
+}
+
+// Default persistence of Filter::Decay
+const DEFAULT_DECAY: f32 = 0.6;
+
+impl FromStr for Filter {
+    type Err = String;
+
+    // Parses "none", "blend", "decay" or "decay:<persistence>"
+    fn from_str(s: &str) -> Result<Filter, String> {
+        match s {
+            "none" => Ok(Filter::None),
+            "blend" => Ok(Filter::Blend),
+            "decay" => Ok(Filter::Decay(DEFAULT_DECAY)),
+            _ => {
+                let persistence = s
+                    .strip_prefix("decay:")
+                    .and_then(|p| p.parse::<f32>().ok())
+                    .filter(|p| (0.0..1.0).contains(p))
+                    .ok_or(format!("Unknown display filter: {}", s))?;
+
+                Ok(Filter::Decay(persistence))
+            }
+        }
+    }
+}
+
+pub struct Display<'a> {
+    canvas: WindowCanvas,
+
+    // 64 x 32 texture holding the latest framebuffer, scaled by the renderer
+    texture: Texture<'a>,
+
+    // Staging buffer for texture uploads
+    pixels: [u8; SCREEN_WIDTH * SCREEN_HEIGHT * BYTES_PER_PIXEL],
+
+    palette: Palette,
+
+    filter: Filter,
+
+    // Previous framebuffer, used by Filter::Blend
+    prev_screen: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+
+    // Whether the last frame drawn with Filter::Blend showed pixels unset
+    // since, which the next frame must clear
+    ghosts: bool,
+
+    // Brightness of each pixel from 0.0 to 1.0, used by Filter::Decay
+    intensity: [f32; SCREEN_WIDTH * SCREEN_HEIGHT],
+
+    // Only scale by whole multiples of the CHIP-8 resolution
+    integer_scaling: bool,
+
+    // Text drawn over the screen, never written to the framebuffer
+    overlay: Overlay,
+}
+
+impl<'a> Display<'a> {
+    pub fn new(
+        canvas: WindowCanvas,
+        texture_creator: &'a TextureCreator<WindowContext>,
+        integer_scaling: bool,
+        palette: Palette,
+        filter: Filter,
+    ) -> Result<Display<'a>, String> {
+        let texture = texture_creator
+            .create_texture_streaming(
+                PixelFormatEnum::RGB24,
+                SCREEN_WIDTH as u32,
+                SCREEN_HEIGHT as u32,
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(Display {
+            canvas,
+            texture,
+            pixels: [0; SCREEN_WIDTH * SCREEN_HEIGHT * BYTES_PER_PIXEL],
+            palette,
+            filter,
+            prev_screen: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            ghosts: false,
+            intensity: [0.0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            integer_scaling,
+            overlay: Overlay::new(Instant::now()),
+        })
+    }
+
+    pub fn overlay_mut(&mut self) -> &mut Overlay {
+        &mut self.overlay
+    }
+
+    pub fn set_title(&mut self, title: &str) -> Result<(), String> {
+        self.canvas
+            .window_mut()
+            .set_title(title)
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    // Takes effect on the next draw
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    // Takes effect on the next draw
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    // Whether pixels are still fading out, so frames must be drawn
+    // even if the framebuffer didn't change
+    pub fn is_fading(&self) -> bool {
+        match self.filter {
+            Filter::None => false,
+            Filter::Blend => self.ghosts,
+            Filter::Decay(_) => self.intensity.iter().any(|i| *i > 0.0 && *i < 1.0),
+        }
+    }
+
+    // Uploads the framebuffer into the texture and presents it
+    pub fn draw(&mut self, screen: &[u8]) -> Result<(), String> {
+        self.ghosts = false;
+        for (i, pixel) in screen.iter().enumerate() {
+            let brightness = match self.filter {
+                Filter::None => (*pixel != 0) as u8 as f32,
+                Filter::Blend => {
+                    self.ghosts |= *pixel == 0 && self.prev_screen[i] != 0;
+                    (*pixel != 0 || self.prev_screen[i] != 0) as u8 as f32
+                }
+                Filter::Decay(persistence) => {
+                    if *pixel != 0 {
+                        self.intensity[i] = 1.0;
+                    } else {
+                        self.intensity[i] *= persistence;
+
+                        // Snap to black once it's no longer visible
+                        if self.intensity[i] < 1.0 / 255.0 {
+                            self.intensity[i] = 0.0;
+                        }
+                    }
+                    self.intensity[i]
+                }
+            };
+            let color = mix(self.palette.off, self.palette.on, brightness);
+            let offset = i * BYTES_PER_PIXEL;
+
+            self.pixels[offset..offset + BYTES_PER_PIXEL].copy_from_slice(&color);
+        }
+        self.prev_screen.copy_from_slice(screen);
+
+        self.texture
+            .update(None, &self.pixels, PITCH)
+            .map_err(|e| e.to_string())?;
+
+        self.present()
+    }
+
+    // Presents the last uploaded frame again, e.g. after the window is resized
+    pub fn present(&mut self) -> Result<(), String> {
+        let (width, height) = self.canvas.output_size()?;
+        let dest = viewport(width, height, self.integer_scaling);
+
+        // Letterbox area
+        let [r, g, b] = self.palette.off;
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, dest)?;
+        self.draw_overlay(dest)?;
+        self.canvas.present();
+
+        Ok(())
+    }
+
+    // Lines of text a menu fits in the window
+    pub fn menu_rows(&self) -> Result<usize, String> {
+        let (_, height) = self.canvas.output_size()?;
+        let size = font_size(height);
+        let line_height = (GLYPH_HEIGHT as i32 + 3) * size;
+
+        Ok(((height as i32 - 2 * size) / line_height).max(1) as usize)
+    }
+
+    // Draws lines of text over the whole window instead of the screen, the
+    // selected line highlighted, and presents them with the overlay
+    pub fn draw_menu(&mut self, lines: &[String], selected: Option<usize>) -> Result<(), String> {
+        let (width, height) = self.canvas.output_size()?;
+        let size = font_size(height);
+        let line_height = (GLYPH_HEIGHT as i32 + 3) * size;
+
+        let [r, g, b] = self.palette.off;
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+        self.canvas.set_blend_mode(BlendMode::Blend);
+
+        for (row, text) in lines.iter().enumerate() {
+            let colors = if selected == Some(row) {
+                [self.palette.off, self.palette.on]
+            } else {
+                [self.palette.on, self.palette.off]
+            };
+            let y = 2 * size + row as i32 * line_height;
+            draw_text(&mut self.canvas, text, 2 * size, y, size, colors)?;
+        }
+
+        self.draw_overlay(Rect::new(0, 0, width, height))?;
+        self.canvas.present();
+
+        Ok(())
+    }
+
+    // Draws the overlay lines in the top corners of the viewport
+    fn draw_overlay(&mut self, dest: Rect) -> Result<(), String> {
+        let size = font_size(dest.height());
+        let line_height = (GLYPH_HEIGHT as i32 + 3) * size;
+        let colors = [self.palette.on, self.palette.off];
+        self.canvas.set_blend_mode(BlendMode::Blend);
+
+        for (row, text) in self.overlay.left().iter().enumerate() {
+            let x = dest.x() + 2 * size;
+            let y = dest.y() + 2 * size + row as i32 * line_height;
+            draw_text(&mut self.canvas, text, x, y, size, colors)?;
+        }
+
+        for (row, text) in self.overlay.right().iter().enumerate() {
+            let width = overlay::text_width(text) as i32 * size;
+            let x = dest.right() - 2 * size - width;
+            let y = dest.y() + 2 * size + row as i32 * line_height;
+            draw_text(&mut self.canvas, text, x, y, size, colors)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Side of a font pixel for an output of the given height
+fn font_size(height: u32) -> i32 {
+    (height / OVERLAY_LINES).max(1) as i32
+}
+
+// Draws text with its top left corner at x, y on a translucent backdrop,
+// size is the side of a font pixel, colors the foreground and backdrop
+pub fn draw_text(
+    canvas: &mut WindowCanvas,
+    text: &str,
+    x: i32,
+    y: i32,
+    size: i32,
+    [foreground, background]: [[u8; 3]; 2],
+) -> Result<(), String> {
+    let width = overlay::text_width(text) as i32 * size;
+    let height = GLYPH_HEIGHT as i32 * size;
+
+    let [r, g, b] = background;
+    canvas.set_draw_color(Color::RGBA(r, g, b, OVERLAY_ALPHA));
+    canvas.fill_rect(Rect::new(
+        x - size,
+        y - size,
+        (width + 2 * size) as u32,
+        (height + 2 * size) as u32,
+    ))?;
+
+    let rects: Vec<Rect> = overlay::text_pixels(text)
+        .into_iter()
+        .map(|(px, py)| {
+            Rect::new(
+                x + px as i32 * size,
+                y + py as i32 * size,
+                size as u32,
+                size as u32,
+            )
+        })
+        .collect();
+
+    let [r, g, b] = foreground;
+    canvas.set_draw_color(Color::RGB(r, g, b));
+    canvas.fill_rects(&rects)
+}
+
+// Largest rect with the CHIP-8 aspect ratio that fits the output, centered
+fn viewport(width: u32, height: u32, integer_scaling: bool) -> Rect {
+    let scale_x = width as f32 / SCREEN_WIDTH as f32;
+    let scale_y = height as f32 / SCREEN_HEIGHT as f32;
+    let mut scale = scale_x.min(scale_y);
+
+    if integer_scaling {
+        // Never go below 1x, even if it means cropping
+        scale = scale.floor().max(1.0);
+    }
+
+    let w = (SCREEN_WIDTH as f32 * scale) as u32;
+    let h = (SCREEN_HEIGHT as f32 * scale) as u32;
+    let x = (width as i32 - w as i32) / 2;
+    let y = (height as i32 - h as i32) / 2;
+
+    Rect::new(x, y, w, h)
+}
+
+// Linear interpolation between two colors, t from 0.0 (a) to 1.0 (b)
+fn mix(a: [u8; 3], b: [u8; 3], t: f32) -> [u8; 3] {
+    let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+
+    [lerp(a[0], b[0]), lerp(a[1], b[1]), lerp(a[2], b[2])]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_from_str() {
+        assert_eq!("none".parse(), Ok(Filter::None));
+        assert_eq!("blend".parse(), Ok(Filter::Blend));
+        assert_eq!("decay".parse(), Ok(Filter::Decay(DEFAULT_DECAY)));
+        assert_eq!("decay:0.25".parse(), Ok(Filter::Decay(0.25)));
+        assert_eq!(
+            "decay:1".parse::<Filter>(),
+            Err("Unknown display filter: decay:1".to_string())
+        );
+        assert!("decay:-0.5".parse::<Filter>().is_err());
+        assert!("glow".parse::<Filter>().is_err());
+    }
+
+    #[test]
+    fn test_viewport() {
+        assert_eq!(viewport(640, 320, false), Rect::new(0, 0, 640, 320));
+
+        // Letterboxed and pillarboxed
+        assert_eq!(viewport(640, 480, false), Rect::new(0, 80, 640, 320));
+        assert_eq!(viewport(800, 320, false), Rect::new(80, 0, 640, 320));
+
+        // 6.25x rounded down to 6x
+        assert_eq!(viewport(400, 200, true), Rect::new(8, 4, 384, 192));
+
+        // At least 1x, cropped
+        assert_eq!(viewport(32, 16, true), Rect::new(-16, -8, 64, 32));
+    }
+
+    #[test]
+    fn test_mix() {
+        let black = [0, 0, 0];
+        let amber = [0xFF, 0xB0, 0x00];
+
+        assert_eq!(mix(black, amber, 0.0), black);
+        assert_eq!(mix(black, amber, 1.0), amber);
+        assert_eq!(mix(black, amber, 0.5), [0x80, 0x58, 0x00]);
+        assert_eq!(mix(amber, black, 0.25), [0xBF, 0x84, 0x00]);
+    }
+}
@@ -0,0 +1,763 @@
+// GDB remote serial protocol stub over TCP, e.g.
+//
+//     chip8_rust --gdb 1234 game.ch8
+//     gdb -ex 'target remote localhost:1234'
+//
+// Registers are v0 - vF (8 bit), I and pc (16 bit) and sp (8 bit), described
+// to GDB with target.xml. Memory addresses are CHIP-8 addresses.
+use crate::chip8::{Chip8, MEM_SIZE, REG_SIZE};
+use std::collections::HashSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+// Register numbers after v0 - vF
+const REG_I: usize = REG_SIZE;
+const REG_PC: usize = REG_SIZE + 1;
+const REG_SP: usize = REG_SIZE + 2;
+
+// Stop reasons, as signal numbers
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+// Packets from GDB, sent one at a time
+const MAX_PACKET_SIZE: usize = 0x1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RunState {
+    // Waiting for commands
+    Halted,
+
+    // Executing until a breakpoint or an interrupt
+    Running,
+}
+
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+
+    // Bytes received but not handled yet
+    input: Vec<u8>,
+
+    no_ack: bool,
+    breakpoints: HashSet<usize>,
+    state: RunState,
+
+    // Breakpoints are ignored for the first instruction after resuming,
+    // otherwise continuing from a breakpoint would stop right away
+    resumed: bool,
+}
+
+impl GdbStub {
+    // Listens on localhost only, the protocol has no authentication
+    pub fn bind(port: u16) -> io::Result<GdbStub> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+
+        Ok(GdbStub {
+            listener,
+            client: None,
+            input: Vec::new(),
+            no_ack: false,
+            breakpoints: HashSet::new(),
+            state: RunState::Halted,
+            resumed: false,
+        })
+    }
+
+    pub fn port(&self) -> io::Result<u16> {
+        Ok(self.listener.local_addr()?.port())
+    }
+
+    // Blocks until GDB connects, the machine is halted until it continues
+    pub fn accept(&mut self) -> io::Result<()> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+
+        self.client = Some(stream);
+        self.input.clear();
+        self.no_ack = false;
+        self.state = RunState::Halted;
+
+        Ok(())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    // Whether the machine may execute instructions
+    pub fn is_running(&self) -> bool {
+        self.client.is_none() || self.state == RunState::Running
+    }
+
+    // Handles pending commands from GDB without blocking
+    pub fn poll(&mut self, chip8: &mut Chip8) -> io::Result<()> {
+        let mut buf = [0; 1024];
+
+        loop {
+            let client = match &mut self.client {
+                Some(client) => client,
+                None => return Ok(()),
+            };
+
+            match client.read(&mut buf) {
+                Ok(0) => {
+                    // GDB went away, let the machine run
+                    self.client = None;
+                    return Ok(());
+                }
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+
+            if self.input.len() > MAX_PACKET_SIZE * 2 {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "GDB packet too large",
+                ));
+            }
+        }
+
+        while let Some(packet) = self.next_packet()? {
+            self.handle(chip8, &packet)?;
+        }
+
+        Ok(())
+    }
+
+    // Executes up to the given number of instructions while running,
+    // stopping at breakpoints. Returns the number executed.
+    pub fn run(&mut self, chip8: &mut Chip8, instructions: u32) -> io::Result<u32> {
+        for executed in 0..instructions {
+            if !self.is_running() {
+                return Ok(executed);
+            }
+
+            if self.client.is_some() && !self.resumed && self.breakpoints.contains(&chip8.pc()) {
+                self.stop(SIGTRAP)?;
+                return Ok(executed);
+            }
+            self.resumed = false;
+
+            if !chip8.step() {
+                if chip8.fault().is_some() && self.client.is_some() {
+                    self.stop(SIGSEGV)?;
+                }
+                return Ok(executed);
+            }
+        }
+
+        Ok(instructions)
+    }
+
+    // Serves one GDB session without a frontend, running freely at the
+    // given speed while GDB lets the machine run
+    pub fn serve(&mut self, chip8: &mut Chip8, ips: u32) -> io::Result<()> {
+        const SLICE: Duration = Duration::from_millis(10);
+
+        self.accept()?;
+        while self.is_connected() {
+            self.poll(chip8)?;
+
+            if self.is_connected() && self.is_running() {
+                self.run(chip8, (ips / 100).max(1))?;
+            }
+            thread::sleep(SLICE);
+        }
+
+        Ok(())
+    }
+
+    // Extracts the next complete packet, acknowledging it.
+    // Returns Some(b"\x03") for an interrupt request.
+    fn next_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let start = match self.input.iter().position(|b| *b == b'$' || *b == 0x03) {
+                Some(start) => start,
+                None => {
+                    // Only acks and noise
+                    self.input.clear();
+                    return Ok(None);
+                }
+            };
+
+            if self.input[start] == 0x03 {
+                self.input.drain(..=start);
+                return Ok(Some(vec![0x03]));
+            }
+
+            let end = match self.input[start..].iter().position(|b| *b == b'#') {
+                Some(end) if start + end + 2 < self.input.len() => start + end,
+                _ => {
+                    // Incomplete, wait for more
+                    self.input.drain(..start);
+                    return Ok(None);
+                }
+            };
+
+            let data = unescape(&self.input[start + 1..end]);
+            let checksum = std::str::from_utf8(&self.input[end + 1..end + 3])
+                .ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok());
+            let valid = checksum == Some(checksum_of(&self.input[start + 1..end]));
+            self.input.drain(..end + 3);
+
+            if !self.no_ack {
+                self.write_raw(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(data));
+            }
+        }
+    }
+
+    fn handle(&mut self, chip8: &mut Chip8, packet: &[u8]) -> io::Result<()> {
+        if packet == [0x03] {
+            if self.state == RunState::Running {
+                self.stop(SIGINT)?;
+            }
+            return Ok(());
+        }
+
+        let (command, args) = match packet.split_first() {
+            Some((command, args)) => (*command, String::from_utf8_lossy(args).into_owned()),
+            None => return self.send("E01"),
+        };
+        let args = args.as_str();
+
+        let reply = match command {
+            b'?' => format!("S{:02x}", SIGTRAP),
+            b'g' => {
+                let mut reply = String::new();
+                for reg in 0..=REG_SP {
+                    reply.push_str(&read_register(chip8, reg));
+                }
+                reply
+            }
+            b'G' => {
+                let bytes = decode_hex(args).unwrap_or_default();
+                if bytes.len() != REG_SIZE + 5 {
+                    "E01".to_string()
+                } else {
+                    let i = u16::from_le_bytes([bytes[REG_SIZE], bytes[REG_SIZE + 1]]);
+                    let pc = u16::from_le_bytes([bytes[REG_SIZE + 2], bytes[REG_SIZE + 3]]);
+                    let sp = bytes[REG_SIZE + 4];
+                    let regs = bytes[..REG_SIZE]
+                        .iter()
+                        .map(|v| *v as usize)
+                        .chain([i as usize, pc as usize, sp as usize])
+                        .enumerate();
+
+                    // Nothing is written unless every register is in range
+                    let valid = regs
+                        .clone()
+                        .all(|(reg, v)| register_in_range(chip8, reg, v));
+                    if valid {
+                        for (reg, value) in regs {
+                            write_register(chip8, reg, value);
+                        }
+                        "OK".to_string()
+                    } else {
+                        "E01".to_string()
+                    }
+                }
+            }
+            b'p' => match usize::from_str_radix(args, 16) {
+                Ok(reg) if reg <= REG_SP => read_register(chip8, reg),
+                _ => "E01".to_string(),
+            },
+            b'P' => match parse_register_write(args) {
+                Some((reg, value)) if reg <= REG_SP && write_register(chip8, reg, value) => {
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            b'm' => match parse_range(args) {
+                Some((addr, end)) if end - addr <= MAX_PACKET_SIZE / 2 => (addr..end)
+                    .map(|a| format!("{:02x}", chip8.peek(a)))
+                    .collect(),
+                _ => "E01".to_string(),
+            },
+            b'M' => {
+                let mut parts = args.splitn(2, ':');
+                match (
+                    parts.next().and_then(parse_range),
+                    parts.next().map(decode_hex),
+                ) {
+                    (Some((addr, end)), Some(Some(bytes))) if bytes.len() == end - addr => {
+                        for (i, byte) in bytes.iter().enumerate() {
+                            chip8.poke(addr + i, *byte);
+                        }
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            b'Z' | b'z' => match parse_breakpoint(args) {
+                Some(addr) => {
+                    if command == b'Z' {
+                        self.breakpoints.insert(addr);
+                    } else {
+                        self.breakpoints.remove(&addr);
+                    }
+                    "OK".to_string()
+                }
+                None => String::new(),
+            },
+            b's' | b'c' if !resume_at(chip8, args) => "E01".to_string(),
+            b's' => {
+                chip8.step();
+                let signal = if chip8.fault().is_some() {
+                    SIGSEGV
+                } else {
+                    SIGTRAP
+                };
+                format!("S{:02x}", signal)
+            }
+            b'c' => {
+                self.state = RunState::Running;
+                self.resumed = true;
+                // Reply is sent when the machine stops
+                return Ok(());
+            }
+            b'D' => {
+                self.send("OK")?;
+                self.client = None;
+                self.breakpoints.clear();
+                return Ok(());
+            }
+            b'k' => {
+                self.client = None;
+                self.breakpoints.clear();
+                return Ok(());
+            }
+            b'H' => "OK".to_string(),
+            b'q' | b'Q' => self.query(&String::from_utf8_lossy(packet)),
+            _ => String::new(),
+        };
+
+        self.send(&reply)
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+",
+                MAX_PACKET_SIZE
+            )
+        } else if packet == "QStartNoAckMode" {
+            // Takes effect after this reply, which is still acked
+            self.no_ack = true;
+            "OK".to_string()
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if packet == "qC" {
+            "QC1".to_string()
+        } else if packet == "qfThreadInfo" {
+            "m1".to_string()
+        } else if packet == "qsThreadInfo" {
+            "l".to_string()
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_range(range) {
+                Some((offset, end)) => {
+                    let xml = TARGET_XML.as_bytes();
+                    let start = offset.min(xml.len());
+                    let end = end.min(xml.len());
+                    let marker = if end == xml.len() { "l" } else { "m" };
+                    format!("{}{}", marker, String::from_utf8_lossy(&xml[start..end]))
+                }
+                None => "E01".to_string(),
+            }
+        } else {
+            String::new()
+        }
+    }
+
+    fn stop(&mut self, signal: u8) -> io::Result<()> {
+        self.state = RunState::Halted;
+        self.send(&format!("S{:02x}", signal))
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.write_raw(packet.as_bytes())
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let client = match &mut self.client {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+
+        // The socket is non-blocking, replies are small enough to retry
+        let mut written = 0;
+        while written < bytes.len() {
+            match client.write(&bytes[written..]) {
+                Ok(n) => written += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::yield_now(),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_register(chip8: &Chip8, reg: usize) -> String {
+    match reg {
+        REG_I => encode_hex(&(chip8.i() as u16).to_le_bytes()),
+        REG_PC => encode_hex(&(chip8.pc() as u16).to_le_bytes()),
+        REG_SP => format!("{:02x}", chip8.sp()),
+        _ => format!("{:02x}", chip8.v()[reg]),
+    }
+}
+
+fn register_in_range(chip8: &Chip8, reg: usize, value: usize) -> bool {
+    match reg {
+        REG_I => value < MEM_SIZE,
+        // Room for the 2 bytes of the next opcode
+        REG_PC => value <= MEM_SIZE - 2,
+        REG_SP => value <= chip8.quirks().stack_depth,
+        _ => reg < REG_SIZE && value <= 0xFF,
+    }
+}
+
+// Returns false if the value is out of range for the register
+fn write_register(chip8: &mut Chip8, reg: usize, value: usize) -> bool {
+    if !register_in_range(chip8, reg, value) {
+        return false;
+    }
+
+    match reg {
+        REG_I => chip8.set_i(value),
+        REG_PC => chip8.set_pc(value),
+        REG_SP => chip8.set_sp(value),
+        _ => chip8.set_v(reg, value as u8),
+    }
+
+    true
+}
+
+// Optional address for s and c to resume from. Returns false if it's out
+// of range.
+fn resume_at(chip8: &mut Chip8, args: &str) -> bool {
+    args.is_empty()
+        || usize::from_str_radix(args, 16).is_ok_and(|addr| write_register(chip8, REG_PC, addr))
+}
+
+// "n=value", value in target byte order
+fn parse_register_write(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, '=');
+    let reg = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let bytes = decode_hex(parts.next()?)?;
+    let value = bytes
+        .iter()
+        .rev()
+        .fold(0_usize, |value, byte| value << 8 | *byte as usize);
+
+    Some((reg, value))
+}
+
+// "addr,len", returned as the start and end of the range
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let addr = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+
+    Some((addr, addr.checked_add(len)?))
+}
+
+// "type,addr,kind", software and hardware breakpoints are handled the same
+fn parse_breakpoint(args: &str) -> Option<usize> {
+    let mut parts = args.split(',');
+    match parts.next()? {
+        "0" | "1" => usize::from_str_radix(parts.next()?, 16).ok(),
+        _ => None,
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0_u8, |sum, b| sum.wrapping_add(*b))
+}
+
+// Undoes the '}' escaping of binary data
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+
+    while let Some(b) = bytes.next() {
+        match b {
+            b'}' => {
+                if let Some(next) = bytes.next() {
+                    result.push(next ^ 0x20);
+                }
+            }
+            _ => result.push(*b),
+        }
+    }
+
+    result
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::PC_START;
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::sync::mpsc;
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        // Sends a packet and returns the reply, acks are skipped
+        fn command(&mut self, data: &str) -> String {
+            self.send(data);
+            self.reply()
+        }
+
+        // Sends a packet without waiting for a reply
+        fn send(&mut self, data: &str) {
+            let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+            self.writer.write_all(packet.as_bytes()).unwrap();
+        }
+
+        fn reply(&mut self) -> String {
+            let mut raw = Vec::new();
+            self.reader.read_until(b'$', &mut raw).unwrap();
+            raw.clear();
+            self.reader.read_until(b'#', &mut raw).unwrap();
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum).unwrap();
+            self.writer.write_all(b"+").unwrap();
+
+            raw.pop();
+            String::from_utf8(raw).unwrap()
+        }
+    }
+
+    // Runs the stub on a separate thread with the given program
+    fn start(program: &[u8]) -> (Client, mpsc::Receiver<Chip8>) {
+        let mut stub = GdbStub::bind(0).unwrap();
+        let port = stub.port().unwrap();
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(program).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            stub.serve(&mut chip8, 6000).unwrap();
+            sender.send(chip8).unwrap();
+        });
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let client = Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        };
+
+        (client, receiver)
+    }
+
+    #[test]
+    fn test_registers() {
+        let (mut client, receiver) = start(&[0x6A, 0x42, 0xA3, 0x21]);
+
+        assert!(client
+            .command("qSupported:swbreak+")
+            .contains("qXfer:features:read+"));
+        assert_eq!(client.command("?"), "S05");
+
+        assert_eq!(client.command("s"), "S05");
+        assert_eq!(client.command("s"), "S05");
+        let registers = client.command("g");
+        assert_eq!(registers.len(), (REG_SIZE + 5) * 2);
+        assert_eq!(&registers[0x14..0x16], "42"); // vA
+        assert_eq!(&registers[32..36], "2103"); // I, little endian
+        assert_eq!(client.command("p11"), "0402"); // pc
+        assert_eq!(client.command("p12"), "00"); // sp
+
+        assert_eq!(client.command("P3=7f"), "OK");
+        assert_eq!(client.command("p3"), "7f");
+        assert_eq!(client.command("P11=0003"), "OK");
+        assert_eq!(client.command("P11=0010"), "E01"); // pc out of memory
+        assert_eq!(client.command("P12=ff"), "E01"); // sp out of stack
+        assert_eq!(client.command("P10=ff0f"), "OK");
+        assert_eq!(client.command("P10=0010"), "E01"); // I out of memory
+
+        // v0-vF 01, I 0x0123, pc 0x0400, sp 0
+        let v = "01".repeat(REG_SIZE);
+        assert_eq!(client.command(&format!("G{}2301000400", v)), "OK");
+        assert_eq!(client.command("p3"), "01");
+        // Nothing is written if any register is out of range
+        let v = "02".repeat(REG_SIZE);
+        assert_eq!(client.command(&format!("G{}0010000300", v)), "E01");
+        assert_eq!(client.command(&format!("G{}2301001000", v)), "E01");
+        assert_eq!(client.command(&format!("G{}23010003ff", v)), "E01");
+        assert_eq!(client.command("p3"), "01");
+
+        client.send("k");
+        let chip8 = receiver.recv().unwrap();
+        assert_eq!(chip8.v()[3], 0x01);
+        assert_eq!(chip8.i(), 0x123);
+        assert_eq!(chip8.pc(), 0x400);
+    }
+
+    #[test]
+    fn test_memory() {
+        let (mut client, receiver) = start(&[0x12, 0x00]);
+
+        assert_eq!(client.command("m200,2"), "1200");
+        assert_eq!(client.command("M300,3:abcdef"), "OK");
+        assert_eq!(client.command("m300,3"), "abcdef");
+        assert_eq!(client.command("M300,3:ab"), "E01");
+
+        client.send("k");
+        assert_eq!(receiver.recv().unwrap().peek(0x302), 0xEF);
+    }
+
+    #[test]
+    fn test_breakpoint_and_continue() {
+        // v0 += 1 in a loop of 2 instructions, then a jump back
+        let (mut client, receiver) = start(&[0x70, 0x01, 0x00, 0xE0, 0x12, 0x00]);
+
+        assert_eq!(client.command("Z0,204,2"), "OK");
+        client.send("c");
+        assert_eq!(client.reply(), "S05");
+        assert_eq!(client.command("p11"), "0402");
+
+        // Continuing from the breakpoint runs a full loop
+        client.send("c");
+        assert_eq!(client.reply(), "S05");
+        assert_eq!(client.command("p0"), "02");
+
+        // Interrupt a free run
+        assert_eq!(client.command("z0,204,2"), "OK");
+        client.send("c");
+        thread::sleep(Duration::from_millis(50));
+        client.writer.write_all(&[0x03]).unwrap();
+        assert_eq!(client.reply(), "S02");
+
+        client.send("k");
+        assert!(receiver.recv().unwrap().v()[0] > 2);
+    }
+
+    #[test]
+    fn test_fault() {
+        let (mut client, _receiver) = start(&[0x00, 0xEE]);
+
+        assert_eq!(client.command("s"), "S0b");
+        client.send("c");
+        assert_eq!(client.reply(), "S0b");
+        assert_eq!(client.command("p11"), "0002");
+
+        client.send("k");
+    }
+
+    #[test]
+    fn test_target_xml() {
+        let (mut client, _receiver) = start(&[0x12, 0x00]);
+
+        let mut xml = String::new();
+        loop {
+            let reply = client.command(&format!(
+                "qXfer:features:read:target.xml:{:x},80",
+                xml.len()
+            ));
+            xml.push_str(&reply[1..]);
+            if reply.starts_with('l') {
+                break;
+            }
+        }
+        assert_eq!(xml, TARGET_XML);
+
+        client.send("k");
+    }
+
+    #[test]
+    fn test_bad_packets() {
+        let (mut client, receiver) = start(&[0x12, 0x00]);
+
+        assert_eq!(client.command(""), "E01");
+        assert_eq!(client.command("sffff"), "E01");
+        assert_eq!(client.command("sfff"), "E01");
+        client.send("cfff");
+        assert_eq!(client.reply(), "E01");
+        assert_eq!(client.command("mffffffffffffffff,2"), "E01");
+        assert_eq!(client.command("M1,ffffffffffffffff:00"), "E01");
+        assert_eq!(
+            client.command("qXfer:features:read:target.xml:1,ffffffffffffffff"),
+            "E01"
+        );
+
+        // Still usable
+        assert_eq!(client.command("s200"), "S05");
+        assert_eq!(client.command("p11"), "0002");
+
+        client.send("k");
+        assert_eq!(receiver.recv().unwrap().pc(), PC_START);
+    }
+
+    #[test]
+    fn test_bad_checksum() {
+        let (mut client, _receiver) = start(&[0x12, 0x00]);
+
+        client.writer.write_all(b"$?#00").unwrap();
+        let mut nack = [0; 1];
+        client.reader.read_exact(&mut nack).unwrap();
+        assert_eq!(&nack, b"-");
+        assert_eq!(client.command("?"), "S05");
+
+        client.send("k");
+    }
+}
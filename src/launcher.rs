@@ -0,0 +1,220 @@
+// ROM browser listing the ROMs of a directory, shown when the ROM given on
+// the command line is a directory or after Escape. Titles and platforms
+// come from the ROM database, or else the file name and extension.
+use chip8_core::platform::Platform;
+use chip8_core::romdb::RomDb;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Longest title shown, longer ones are cut
+const TITLE_WIDTH: usize = 40;
+
+pub struct Entry {
+    pub path: PathBuf,
+    pub title: String,
+
+    // None for unknown .ch8 files, which run with the default quirks
+    pub platform: Option<Platform>,
+}
+
+impl Entry {
+    // None if the file isn't a ROM
+    fn of(path: PathBuf, db: &RomDb) -> Option<Entry> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        let mut platform = match extension.as_str() {
+            "ch8" => None,
+            "sc8" => Some(Platform::Schip),
+            "xo8" => Some(Platform::XoChip),
+            _ => return None,
+        };
+        let mut title = path.file_stem()?.to_string_lossy().into_owned();
+
+        let rom = fs::read(&path).unwrap_or_default();
+        if let Some(info) = db.lookup(&rom) {
+            title = info.title.clone().unwrap_or(title);
+            platform = info.platform.or(platform);
+        }
+
+        Some(Entry {
+            path,
+            title,
+            platform,
+        })
+    }
+
+    fn platform_name(&self) -> &'static str {
+        match self.platform {
+            None | Some(Platform::Chip8) => "CHIP-8",
+            Some(Platform::Schip) => "SUPER-CHIP",
+            Some(Platform::XoChip) => "XO-CHIP",
+        }
+    }
+}
+
+pub struct Launcher {
+    dir: PathBuf,
+
+    // Sorted by title
+    entries: Vec<Entry>,
+
+    selected: usize,
+
+    // First entry shown
+    top: usize,
+}
+
+impl Launcher {
+    // Lists the .ch8, .sc8 and .xo8 files of the directory
+    pub fn open(dir: &Path, db: &RomDb) -> io::Result<Launcher> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                entries.extend(Entry::of(entry.path(), db));
+            }
+        }
+        entries.sort_by_key(|e| e.title.to_lowercase());
+
+        Ok(Launcher {
+            dir: dir.to_path_buf(),
+            entries,
+            selected: 0,
+            top: 0,
+        })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+
+    // Moves the selection by delta entries, stopping at the ends
+    pub fn move_by(&mut self, delta: isize) {
+        let last = self.entries.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize;
+    }
+
+    // Text of the header and of the entries fitting in rows lines, and the
+    // line of the selection. Scrolls to keep the selection visible.
+    pub fn lines(&mut self, rows: usize) -> (Vec<String>, Option<usize>) {
+        let mut lines = vec![format!("ROMs in {}", self.dir.display())];
+        if self.entries.is_empty() {
+            lines.push("No .ch8, .sc8 or .xo8 files".to_string());
+            return (lines, None);
+        }
+
+        // The header takes a line
+        let rows = rows.saturating_sub(1).max(1);
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + rows {
+            self.top = self.selected + 1 - rows;
+        }
+
+        for entry in self.entries.iter().skip(self.top).take(rows) {
+            let title: String = entry.title.chars().take(TITLE_WIDTH).collect();
+            lines.push(format!(
+                "{:<width$}  {}",
+                title,
+                entry.platform_name(),
+                width = TITLE_WIDTH
+            ));
+        }
+
+        (lines, Some(self.selected - self.top + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::romdb;
+    use chip8_core::sha1::sha1;
+    use std::env;
+
+    fn launcher(name: &str, files: &[&str]) -> Launcher {
+        launcher_with(name, files, &RomDb::new())
+    }
+
+    fn launcher_with(name: &str, files: &[&str], db: &RomDb) -> Launcher {
+        let dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub.ch8")).unwrap();
+        for file in files {
+            fs::write(dir.join(file), [0x12, 0x00]).unwrap();
+        }
+
+        Launcher::open(&dir, db).unwrap()
+    }
+
+    #[test]
+    fn test_open() {
+        let launcher = launcher(
+            "chip8_rust_test_launcher",
+            &["pong.ch8", "Blinky.SC8", "notes.txt", "alien.xo8"],
+        );
+
+        let entries: Vec<_> = launcher
+            .entries()
+            .iter()
+            .map(|e| (e.title.as_str(), e.platform))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("alien", Some(Platform::XoChip)),
+                ("Blinky", Some(Platform::Schip)),
+                ("pong", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rom_db() {
+        let hash = romdb::to_hex(&sha1(&[0x12, 0x00]));
+        let db = RomDb::parse(&format!("[{}]\ntitle = Spin\nplatform = xochip\n", hash)).unwrap();
+        let launcher = launcher_with("chip8_rust_test_launcher_db", &["spin.ch8"], &db);
+
+        let entry = &launcher.entries()[0];
+        assert_eq!(entry.title, "Spin");
+        assert_eq!(entry.platform, Some(Platform::XoChip));
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut launcher = launcher(
+            "chip8_rust_test_launcher_scroll",
+            &["a.ch8", "b.ch8", "c.ch8", "d.ch8"],
+        );
+
+        launcher.move_by(-1);
+        assert_eq!(launcher.selected().unwrap().title, "a");
+
+        launcher.move_by(2);
+        let (lines, selected) = launcher.lines(3);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("b "));
+        assert!(lines[2].starts_with("c "));
+        assert!(lines[2].ends_with("  CHIP-8"));
+        assert_eq!(selected, Some(2));
+
+        launcher.move_by(10);
+        assert_eq!(launcher.selected().unwrap().title, "d");
+        let (lines, selected) = launcher.lines(3);
+        assert!(lines[2].starts_with("d "));
+        assert_eq!(selected, Some(2));
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut launcher = launcher("chip8_rust_test_launcher_empty", &[]);
+
+        assert!(launcher.selected().is_none());
+        launcher.move_by(1);
+        assert_eq!(launcher.lines(10).1, None);
+    }
+}
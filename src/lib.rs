@@ -0,0 +1,32 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod cheat;
+pub mod chip8;
+#[cfg(feature = "std")]
+pub mod coverage;
+pub mod disasm;
+#[cfg(feature = "std")]
+pub mod gdb;
+pub mod palette;
+#[cfg(feature = "alloc")]
+pub mod patch;
+pub mod platform;
+#[cfg(feature = "std")]
+pub mod profile;
+#[cfg(feature = "std")]
+pub mod recorder;
+mod rng;
+#[cfg(feature = "std")]
+pub mod romdb;
+#[cfg(feature = "std")]
+pub mod screenshot;
+pub mod sha1;
+pub mod timing;
+#[cfg(feature = "std")]
+pub mod trace;
+
+pub use crate::chip8::{Chip8, LoadError, SCREEN_HEIGHT, SCREEN_WIDTH};
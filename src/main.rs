@@ -1,92 +1,1124 @@
-mod chip8;
-use crate::chip8::*;
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
+mod audio;
+mod cli;
+mod console;
+mod display;
+mod launcher;
+mod memview;
+mod overlay;
+mod speed;
+mod spriteview;
+use crate::audio::Beeper;
+use crate::cli::{Command, Options, MAX_IPS};
+use crate::console::Console;
+use crate::display::{Display, Filter};
+use crate::launcher::Launcher;
+use crate::memview::MemoryWindow;
+use crate::speed::Speed;
+use crate::spriteview::SpriteWindow;
+use chip8_core::cheat::Cheats;
+use chip8_core::chip8::PC_START;
+use chip8_core::coverage;
+use chip8_core::gdb::GdbStub;
+use chip8_core::palette::Palette;
+use chip8_core::patch;
+use chip8_core::platform::Platform;
+use chip8_core::profile::Profiler;
+use chip8_core::recorder::{self, Recorder};
+use chip8_core::romdb::RomDb;
+use chip8_core::screenshot;
+use chip8_core::timing::VipTiming;
+use chip8_core::trace::Tracer;
+use chip8_core::{Chip8, SCREEN_HEIGHT, SCREEN_WIDTH};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::EventPump;
 use std::env;
-// use std::io; // Debug
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-const SCALE: u32 = 10;
-const SCALED_WIDTH: u32 = SCREEN_WIDTH as u32 * SCALE;
-const SCALED_HEIGHT: u32 = SCREEN_HEIGHT as u32 * SCALE;
+const FRAME_RATE: u32 = recorder::FRAME_RATE;
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / FRAME_RATE as u64);
+
+// Instructions per second when neither --ips nor the ROM database give any
+const DEFAULT_IPS: u32 = 120;
+
+// Optional debugging tools hooked into the frame loop
+struct Tools {
+    gdb: Option<GdbStub>,
+    tracer: Option<Tracer<Box<dyn Write>>>,
+
+    // --draw-log file
+    draw_log: Option<Box<dyn Write>>,
+
+    profiler: Option<Profiler>,
+    timing: Option<VipTiming>,
+}
+
+// The loaded ROM and its settings, from the options or else the ROM database
+struct Rom {
+    size: usize,
+    ips: u32,
+    palette: Palette,
+    filter: Filter,
+
+    // Keyboard keys mapped to CHIP-8 keys, checked before the default layout
+    keymap: Vec<(Keycode, usize)>,
+
+    // Freezes applied every frame, from the cheat file of the ROM
+    cheats: Cheats,
+}
+
+impl Rom {
+    // Settings while no ROM is loaded, e.g. in the launcher
+    fn none(options: &Options) -> Rom {
+        Rom {
+            size: 0,
+            ips: options.ips.unwrap_or(DEFAULT_IPS),
+            palette: options.palette.unwrap_or_default(),
+            filter: options.filter.unwrap_or(Filter::None),
+            keymap: Vec::new(),
+            cheats: Cheats::new(),
+        }
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    let mut my_chip8 = Chip8::init();
+    let options = match cli::parse(&args) {
+        Ok(Command::Run(options)) => *options,
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut db = RomDb::bundled().clone();
+    if let Some(path) = &options.rom_db {
+        match RomDb::load(path) {
+            Ok(user) => db.merge(user),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let mut my_chip8 = new_chip8(&options);
+    let mut rom = Rom::none(&options);
+    let mut launcher = None;
+
+    if options.rom.is_dir() {
+        if options.headless {
+            eprintln!("error: --headless needs a ROM file, not a directory");
+            process::exit(2);
+        }
+        if options.patch.is_some() {
+            eprintln!("error: --patch needs a ROM file, not a directory");
+            process::exit(2);
+        }
+
+        match Launcher::open(&options.rom, &db) {
+            Ok(l) => launcher = Some(l),
+            Err(e) => {
+                eprintln!("error: Can't list {}: {}", options.rom.display(), e);
+                process::exit(1);
+            }
+        }
+    } else {
+        let patch = options.patch.as_deref();
+        match load(&options, &db, &options.rom, None, patch) {
+            Ok((chip8, r)) => {
+                my_chip8 = chip8;
+                rom = r;
+            }
+            Err(e) => {
+                eprintln!("error: Can't load {}: {}", options.rom.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let mut recorder = match &options.record {
+        Some(path) => {
+            match Recorder::start(
+                path,
+                options.scale as usize,
+                &rom.palette,
+                options.record_audio,
+            ) {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    eprintln!("error: Can't record to {}: {}", path.display(), e);
+                    process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
+    let tracer = match start_tracer(&options) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("error: Can't trace: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let draw_log = options
+        .draw_log
+        .as_ref()
+        .map(|path| match File::create(path) {
+            Ok(f) => Box::new(BufWriter::new(f)) as Box<dyn Write>,
+            Err(e) => {
+                eprintln!("error: Can't log draws to {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        });
+
+    let gdb = options.gdb.map(|port| match wait_gdb(port) {
+        Ok(stub) => stub,
+        Err(e) => {
+            eprintln!("error: Can't wait for GDB on port {}: {}", port, e);
+            process::exit(1);
+        }
+    });
+
+    let mut tools = Tools {
+        gdb,
+        tracer,
+        draw_log,
+        profiler: if options.profile || options.coverage.is_some() {
+            Some(Profiler::new())
+        } else {
+            None
+        },
+        timing: if options.vip_timing {
+            Some(VipTiming::new())
+        } else {
+            None
+        },
+    };
+
+    if options.headless {
+        run_headless(&options, &mut my_chip8, &rom, &mut recorder, &mut tools);
+    } else {
+        run(
+            &options,
+            &db,
+            &mut my_chip8,
+            &mut rom,
+            launcher,
+            &mut recorder,
+            &mut tools,
+        );
+    }
+
+    if let Some(r) = recorder {
+        finish_recording(r);
+    }
+    if let Some(p) = tools.profiler {
+        if options.profile {
+            if let Err(e) = p.report(io::stdout().lock()) {
+                eprintln!("Failed to print profile: {}", e);
+            }
+        }
+
+        if let Some(path) = &options.coverage {
+            let range = PC_START..PC_START + rom.size;
+            match coverage::save(path, &p, my_chip8.memory(), range) {
+                Ok(()) => println!("Coverage saved to {}", path.display()),
+                Err(e) => eprintln!("Failed to save coverage: {}", e),
+            }
+        }
+    }
+
+    if let Some(log) = &mut tools.draw_log {
+        if let Err(e) = log.flush() {
+            eprintln!("Failed to write the draw log: {}", e);
+        }
+    }
+
+    if my_chip8.fault().is_some() {
+        process::exit(1);
+    }
+}
+
+// What the user did in the launcher during a frame
+enum Picked {
+    Nothing,
+    Quit,
+    Rom(Box<Chip8>, Rom),
+}
+
+// Shows the launcher instead of the ROM while launcher is set.
+// rom is updated when a ROM is picked.
+fn run(
+    options: &Options,
+    db: &RomDb,
+    my_chip8: &mut Chip8,
+    rom: &mut Rom,
+    mut launcher: Option<Launcher>,
+    recorder: &mut Option<Recorder>,
+    tools: &mut Tools,
+) {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
-        .window("chip8-rust", SCALED_WIDTH, SCALED_HEIGHT)
+        .window(
+            "chip8-rust",
+            SCREEN_WIDTH as u32 * options.scale,
+            SCREEN_HEIGHT as u32 * options.scale,
+        )
         .position_centered()
+        .resizable()
         .build()
         .unwrap();
-    let mut canvas = window.into_canvas().build().unwrap();
+    let canvas = window.into_canvas().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut display = Display::new(
+        canvas,
+        &texture_creator,
+        options.integer_scaling,
+        rom.palette,
+        rom.filter,
+    )
+    .unwrap();
 
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
-    canvas.clear();
-    canvas.present();
+    display.draw(my_chip8.screen()).unwrap();
 
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut beeper = if options.mute {
+        None
+    } else {
+        match sdl_context.audio().and_then(|audio| Beeper::new(&audio)) {
+            Ok(b) => Some(b),
+            Err(e) => {
+                eprintln!("Sound disabled: {}", e);
+                None
+            }
+        }
+    };
 
-    my_chip8.load_game(&args[1]).unwrap();
-    // let mut buffer = String::new(); // Debug
-    // let mut d = 1; // Debug
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut paused = options.start_paused;
+    let mut advance = false;
+    let mut speed = Speed::new(rom.ips);
+    let mut title = String::new();
+    let mut budget = 0;
+    let mut frames = 0;
+    let mut next_frame = Instant::now();
+    let mut memory_window: Option<MemoryWindow> = None;
+    let mut sprite_window: Option<SpriteWindow> = None;
+    let mut console: Option<Console> = None;
 
     'running: loop {
+        if let Some(l) = &mut launcher {
+            match run_launcher(l, options, db, &mut event_pump, &mut display) {
+                Picked::Nothing => {}
+                Picked::Quit => break 'running,
+                Picked::Rom(chip8, r) => {
+                    *my_chip8 = *chip8;
+                    *rom = r;
+                    launcher = None;
+                    budget = 0;
+                    speed = Speed::new(rom.ips);
+                    display.set_palette(rom.palette);
+                    display.set_filter(rom.filter);
+
+                    // Profile the new ROM only
+                    if tools.profiler.is_some() {
+                        tools.profiler = Some(Profiler::new());
+                    }
+                    display.draw(my_chip8.screen()).unwrap();
+                }
+            }
+
+            if launcher.is_some() {
+                if let Some(b) = &mut beeper {
+                    b.set_beeping(false);
+                }
+                set_title(&mut display, &mut title, "chip8-rust".to_string());
+
+                thread::sleep(FRAME_DURATION);
+                next_frame = Instant::now();
+                continue;
+            }
+        }
+
         for event in event_pump.poll_iter() {
             match event {
-                Event::Quit { .. } => {
+                event if memory_window.as_ref().is_some_and(|w| w.owns(&event)) => {
+                    let w = memory_window.as_mut().unwrap();
+                    if !w.handle(&event, my_chip8, paused) {
+                        memory_window = None;
+                    }
+                }
+                event if sprite_window.as_ref().is_some_and(|w| w.owns(&event)) => {
+                    let w = sprite_window.as_mut().unwrap();
+                    if !w.handle(&event, my_chip8) {
+                        sprite_window = None;
+                    }
+                }
+                Event::KeyDown { .. } | Event::KeyUp { .. } | Event::TextInput { .. }
+                    if console.is_some() =>
+                {
+                    let c = console.as_mut().unwrap();
+                    let file = Cheats::path(&options.cheat_dir, my_chip8.rom_sha1());
+                    let open =
+                        console_event(c, &event, my_chip8, &mut rom.cheats, &file, &mut display);
+                    if !open {
+                        console = None;
+                        video_subsystem.text_input().stop();
+                        display.overlay_mut().set_prompt(None);
+                    }
+                }
+                Event::Quit { .. }
+                | Event::Window {
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
                     break 'running;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => match Launcher::open(&launcher_dir(&options.rom), db) {
+                    Ok(l) => {
+                        // The launcher takes all the events
+                        memory_window = None;
+                        sprite_window = None;
+                        launcher = Some(l);
+                        *my_chip8 = new_chip8(options);
+                        *rom = Rom::none(options);
+                        display.set_palette(rom.palette);
+                        display.set_filter(rom.filter);
+                    }
+                    Err(e) => {
+                        eprintln!("Can't open the launcher: {}", e);
+                        let overlay = display.overlay_mut();
+                        overlay.show_message("Can't open the launcher", Instant::now());
+                    }
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => {
+                    let message = take_screenshot(my_chip8, display.palette(), options.scale);
+                    display.overlay_mut().show_message(&message, Instant::now());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => {
+                    let recording = recorder.is_some();
+                    *recorder = toggle_recording(recorder.take(), display.palette(), options.scale);
+                    let message = match (recording, recorder.is_some()) {
+                        (true, _) => "Recording finished",
+                        (false, true) => "Recording",
+                        (false, false) => "Recording failed",
+                    };
+                    display.overlay_mut().show_message(message, Instant::now());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F1),
+                    ..
+                } => display.overlay_mut().toggle_stats(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    ..
+                } => {
+                    memory_window = match memory_window {
+                        Some(_) => None,
+                        None => match MemoryWindow::open(&video_subsystem) {
+                            Ok(w) => Some(w),
+                            Err(e) => {
+                                eprintln!("Can't open the memory viewer: {}", e);
+                                None
+                            }
+                        },
+                    };
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => {
+                    sprite_window = match sprite_window {
+                        Some(_) => None,
+                        None => match SpriteWindow::open(&video_subsystem) {
+                            Ok(w) => Some(w),
+                            Err(e) => {
+                                eprintln!("Can't open the sprite viewer: {}", e);
+                                None
+                            }
+                        },
+                    };
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    ..
+                } => {
+                    let c = Console::new();
+                    display.overlay_mut().set_prompt(Some(c.prompt()));
+                    console = Some(c);
+                    video_subsystem.text_input().start();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    keymod,
+                    ..
+                } => {
+                    let message = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        my_chip8.hard_reset();
+                        "Hard reset"
+                    } else {
+                        my_chip8.reset();
+                        "Reset"
+                    };
+                    budget = 0;
+                    display.overlay_mut().show_message(message, Instant::now());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Pause),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => paused = !paused,
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } if paused => advance = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => speed.set_fast_forward(true),
+                Event::KeyUp {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => speed.set_fast_forward(false),
+                Event::KeyDown {
+                    keycode: Some(Keycode::RightBracket),
+                    ..
+                } => {
+                    speed.faster();
+                    display
+                        .overlay_mut()
+                        .show_message(&speed.label(), Instant::now());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::LeftBracket),
+                    ..
+                } => {
+                    speed.slower();
+                    display
+                        .overlay_mut()
+                        .show_message(&speed.label(), Instant::now());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Equals),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::KpPlus),
+                    ..
+                } => {
+                    speed.increase_ips();
+                    display
+                        .overlay_mut()
+                        .show_message(&speed.label(), Instant::now());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Minus),
+                    ..
+                }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::KpMinus),
+                    ..
+                } => {
+                    speed.decrease_ips();
+                    display
+                        .overlay_mut()
+                        .show_message(&speed.label(), Instant::now());
+                }
                 Event::KeyDown {
                     keycode: Some(k), ..
-                } => key_press(k, &mut my_chip8),
+                } => key_press(k, my_chip8, &rom.keymap),
                 Event::KeyUp {
                     keycode: Some(k), ..
-                } => key_release(k, &mut my_chip8),
+                } => key_release(k, my_chip8, &rom.keymap),
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                }
+                | Event::Window {
+                    win_event: WindowEvent::Exposed,
+                    ..
+                } => display.present().unwrap(),
                 _ => {}
             }
         }
-        // io::stdin()
-        // .read_line(&mut buffer)
-        // .expect("Failed to read line"); // Debug
 
-        if my_chip8.key_to_wait_reg == None {
-            my_chip8.emulate();
+        let mut executed = 0;
+        if !paused || advance {
+            advance = false;
+            executed = run_frame(my_chip8, options, speed.ips(), &mut budget, tools);
+            rom.cheats.apply(my_chip8);
+            if halted(my_chip8, tools) {
+                break 'running;
+            }
+
+            if let Err(e) = capture(recorder, my_chip8) {
+                eprintln!("Recording stopped: {}", e);
+                *recorder = None;
+            }
+
+            frames += 1;
+            if options.frames == Some(frames) {
+                break 'running;
+            }
+        }
+
+        if let Some(b) = &mut beeper {
+            b.set_beeping(!paused && my_chip8.is_beeping());
+        }
+
+        let now = Instant::now();
+        let overlay = display.overlay_mut();
+        overlay.set_paused(paused);
+        overlay.count_frame(executed, now);
+        overlay.update(now);
+        let overlay_changed = overlay.take_changed();
+
+        if my_chip8.draw_flag() || display.is_fading() {
+            display.draw(my_chip8.screen()).unwrap();
+            my_chip8.clear_draw_flag();
+        } else if overlay_changed {
+            display.present().unwrap();
         }
-        // println!("pc: {:02X} - {:04X}", d, my_chip8.opcode); // Debug
 
-        if my_chip8.draw_flag {
-            draw(&mut canvas, &my_chip8.screen);
-            my_chip8.draw_flag = false;
+        if let Some(w) = &mut memory_window {
+            w.draw(my_chip8, display.palette(), paused).unwrap();
         }
+        if let Some(w) = &mut sprite_window {
+            w.draw(my_chip8, display.palette()).unwrap();
+        }
+
+        let status = if paused {
+            format!("chip8-rust - {} (paused)", speed.label())
+        } else {
+            format!("chip8-rust - {}", speed.label())
+        };
+        set_title(&mut display, &mut title, status);
 
-        thread::sleep(Duration::new(0, 500_000_000u32 / 60));
-        // d += 1; // Debug
+        let now = Instant::now();
+        match speed.frame_duration(FRAME_DURATION) {
+            Some(duration) => {
+                next_frame += duration;
+                if next_frame > now {
+                    thread::sleep(next_frame - now);
+                } else {
+                    // Running behind, don't try to catch up
+                    next_frame = now;
+                }
+            }
+            None => next_frame = now,
+        }
     }
 }
 
-fn key_press(code: Keycode, emu: &mut Chip8) {
-    if let Some(y) = reg_keycode(code) {
-        emu.key[y] = true;
-        if let Some(x) = emu.key_to_wait_reg {
-            emu.v[x] = y as u8;
-            emu.key_to_wait_reg = None;
+// Handles the launcher keys and draws it
+fn run_launcher(
+    launcher: &mut Launcher,
+    options: &Options,
+    db: &RomDb,
+    event_pump: &mut EventPump,
+    display: &mut Display,
+) -> Picked {
+    let rows = display.menu_rows().unwrap();
+    let page = rows as isize - 1;
+    let all = launcher.entries().len() as isize;
+
+    for event in event_pump.poll_iter() {
+        let keycode = match event {
+            Event::Quit { .. } => return Picked::Quit,
+            Event::KeyDown {
+                keycode: Some(k), ..
+            } => k,
+            _ => continue,
+        };
+
+        match keycode {
+            Keycode::Up => launcher.move_by(-1),
+            Keycode::Down => launcher.move_by(1),
+            Keycode::PageUp => launcher.move_by(-page),
+            Keycode::PageDown => launcher.move_by(page),
+            Keycode::Home => launcher.move_by(-all),
+            Keycode::End => launcher.move_by(all),
+            Keycode::Return | Keycode::KpEnter => {
+                if let Some(entry) = launcher.selected() {
+                    match load(options, db, &entry.path, entry.platform, None) {
+                        Ok((chip8, rom)) => return Picked::Rom(Box::new(chip8), rom),
+                        Err(e) => {
+                            eprintln!("error: Can't load {}: {}", entry.path.display(), e);
+                            let overlay = display.overlay_mut();
+                            overlay.show_message("Can't load the ROM", Instant::now());
+                        }
+                    }
+                }
+            }
+            _ => {}
         }
     }
+
+    display.overlay_mut().update(Instant::now());
+    let (lines, selected) = launcher.lines(rows);
+    display.draw_menu(&lines, selected).unwrap();
+
+    Picked::Nothing
+}
+
+// Loads a ROM into a new machine. Its settings come from the options,
+// then the ROM database, then the platform of its extension. Without a
+// patch one next to the ROM is applied, unless --no-patch.
+fn load(
+    options: &Options,
+    db: &RomDb,
+    path: &Path,
+    platform: Option<Platform>,
+    patch: Option<&Path>,
+) -> Result<(Chip8, Rom), Box<dyn std::error::Error>> {
+    let patch = match patch {
+        Some(patch) => Some(patch.to_path_buf()),
+        None if options.no_patch => None,
+        None => patch::find(path),
+    };
+
+    let mut my_chip8 = new_chip8(options);
+    let size = my_chip8.load_game_with(&path.to_string_lossy(), patch.as_deref(), db)?;
+    if let Some(patch) = &patch {
+        println!("Applied {}", patch.display());
+    }
+
+    let info = db.get(my_chip8.rom_sha1()).cloned().unwrap_or_default();
+    let rom_quirks = info
+        .platform_quirks()
+        .or_else(|| platform.map(Platform::quirks));
+    my_chip8.set_quirks(options.quirks_for(rom_quirks));
+
+    // Loaded again as the memory map of the quirks may leave less room
+    my_chip8.reload_rom()?;
+
+    let mut keymap = Vec::new();
+    for (name, key) in &info.keys {
+        match Keycode::from_name(name) {
+            Some(keycode) => keymap.push((keycode, *key)),
+            None => eprintln!("Unknown key in the ROM database: {}", name),
+        }
+    }
+
+    if let Some(title) = &info.title {
+        match &info.author {
+            Some(author) => println!("Loaded {} by {}", title, author),
+            None => println!("Loaded {}", title),
+        }
+    }
+
+    let filter = options.filter.or_else(|| {
+        let name = info.filter.as_ref()?;
+        name.parse()
+            .map_err(|_| eprintln!("Unknown filter in the ROM database: {}", name))
+            .ok()
+    });
+
+    let cheat_file = Cheats::path(&options.cheat_dir, my_chip8.rom_sha1());
+    let cheats = if cheat_file.exists() {
+        Cheats::load(&cheat_file).unwrap_or_else(|e| {
+            eprintln!("Cheats not loaded: {}", e);
+            Cheats::new()
+        })
+    } else {
+        Cheats::new()
+    };
+
+    let rom = Rom {
+        size,
+        ips: options.ips.or(info.ips).unwrap_or(DEFAULT_IPS).min(MAX_IPS),
+        palette: options.palette.or(info.palette).unwrap_or_default(),
+        filter: filter.unwrap_or(Filter::None),
+        keymap,
+        cheats,
+    };
+    Ok((my_chip8, rom))
+}
+
+// Directory the launcher lists, the ROM's own directory for a file
+fn launcher_dir(rom: &Path) -> PathBuf {
+    if rom.is_dir() {
+        return rom.to_path_buf();
+    }
+
+    match rom.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// Machine set up from the options, before loading a ROM
+fn new_chip8(options: &Options) -> Chip8 {
+    let mut my_chip8 = Chip8::init();
+    my_chip8.set_quirks(options.quirks_for(None));
+    // Frames run at 60 Hz whatever the speed, the timers tick once per frame
+    my_chip8.set_timers_per_frame(true);
+    if let Some(seed) = options.seed {
+        my_chip8.set_seed(seed);
+    }
+
+    my_chip8
 }
 
-fn key_release(code: Keycode, emu: &mut Chip8) {
-    if let Some(y) = reg_keycode(code) {
-        emu.key[y] = false;
+fn set_title(display: &mut Display, title: &mut String, status: String) {
+    if status != *title {
+        display.set_title(&status).unwrap();
+        *title = status;
     }
 }
 
+// Runs as fast as possible without a window, e.g. to record a ROM
+fn run_headless(
+    options: &Options,
+    my_chip8: &mut Chip8,
+    rom: &Rom,
+    recorder: &mut Option<Recorder>,
+    tools: &mut Tools,
+) {
+    let mut budget = 0;
+    let mut frames = 0;
+
+    while options.frames != Some(frames) {
+        if tools.gdb.as_ref().is_some_and(|stub| !stub.is_running()) {
+            // Halted by GDB, don't spin
+            thread::sleep(FRAME_DURATION);
+        }
+
+        run_frame(my_chip8, options, rom.ips, &mut budget, tools);
+        rom.cheats.apply(my_chip8);
+        my_chip8.clear_draw_flag();
+        if halted(my_chip8, tools) {
+            break;
+        }
+
+        if let Err(e) = capture(recorder, my_chip8) {
+            eprintln!("error: Recording stopped: {}", e);
+            process::exit(1);
+        }
+
+        frames += 1;
+    }
+}
+
+// Whether a fault halted the machine, GDB reports faults itself
+fn halted(my_chip8: &Chip8, tools: &Tools) -> bool {
+    match my_chip8.fault() {
+        Some(fault) if tools.gdb.is_none() => {
+            eprintln!("error: {} at 0x{:03X}", fault, my_chip8.pc());
+            true
+        }
+        _ => false,
+    }
+}
+
+// Executes the instructions of one 60 Hz frame and ticks the timers once,
+// returns how many ran.
+// budget carries the remainder when ips isn't a multiple of the frame rate,
+// --vip-timing budgets machine cycles instead.
+fn run_frame(
+    my_chip8: &mut Chip8,
+    options: &Options,
+    ips: u32,
+    budget: &mut u32,
+    tools: &mut Tools,
+) -> u32 {
+    *budget += ips;
+    let instructions = *budget / FRAME_RATE;
+    *budget %= FRAME_RATE;
+
+    if let Some(stub) = &mut tools.gdb {
+        let result = stub.poll(my_chip8).and_then(|()| {
+            // Time stops while GDB halts the machine
+            let running = stub.is_running();
+            let executed = stub.run(my_chip8, instructions)?;
+            if running {
+                my_chip8.tick_timers();
+            }
+
+            Ok(executed)
+        });
+
+        return match result {
+            Ok(executed) => executed,
+            Err(e) => {
+                eprintln!("GDB connection lost: {}", e);
+                tools.gdb = None;
+                0
+            }
+        };
+    }
+
+    let Tools {
+        tracer,
+        draw_log,
+        profiler,
+        timing,
+        ..
+    } = tools;
+
+    let executed = match timing {
+        Some(timing) => timing.run_frame_with(my_chip8, |chip8| {
+            step(chip8, options, tracer, draw_log, profiler)
+        }),
+        None if !options.debugger
+            && tracer.is_none()
+            && draw_log.is_none()
+            && profiler.is_none() =>
+        {
+            my_chip8.run_frame(instructions)
+        }
+        None => {
+            let executed = (0..instructions)
+                .take_while(|_| step(my_chip8, options, tracer, draw_log, profiler))
+                .count() as u32;
+            my_chip8.tick_timers();
+
+            executed
+        }
+    };
+
+    if let Some(profiler) = profiler {
+        profiler.end_frame();
+    }
+
+    executed
+}
+
+// Executes one instruction through the debugging tools, returns false if
+// none was executed, e.g. while waiting for a key
+fn step(
+    my_chip8: &mut Chip8,
+    options: &Options,
+    tracer: &mut Option<Tracer<Box<dyn Write>>>,
+    draw_log: &mut Option<Box<dyn Write>>,
+    profiler: &mut Option<Profiler>,
+) -> bool {
+    if my_chip8.is_waiting_for_key() {
+        return false;
+    }
+
+    if options.debugger {
+        wait_debugger(my_chip8);
+    }
+    if let Some(profiler) = profiler {
+        profiler.record(my_chip8);
+    }
+
+    let executed = match tracer {
+        Some(t) => match t.step(my_chip8) {
+            Ok(executed) => executed,
+            Err(e) => {
+                eprintln!("Trace stopped: {}", e);
+                *tracer = None;
+                my_chip8.fault().is_none()
+            }
+        },
+        None => my_chip8.step(),
+    };
+
+    if let Some(log) = draw_log {
+        let drew = executed && my_chip8.opcode() & 0xF000 == 0xD000;
+        if let Some(draw) = my_chip8.last_draw().filter(|_| drew) {
+            if let Err(e) = writeln!(log, "{}", draw) {
+                eprintln!("Draw log stopped: {}", e);
+                *draw_log = None;
+            }
+        }
+    }
+
+    executed
+}
+
+// Traces to the --trace file, or only to stderr on a crash with --trace-last
+fn start_tracer(options: &Options) -> io::Result<Option<Tracer<Box<dyn Write>>>> {
+    let out: Box<dyn Write> = match &options.trace {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None if options.trace_last > 0 => Box::new(io::stderr()),
+        None => return Ok(None),
+    };
+
+    let mut tracer = Tracer::new(out);
+    tracer.set_ring_size(options.trace_last);
+    if let Some(range) = &options.trace_range {
+        tracer.set_range(range.clone());
+    }
+
+    Ok(Some(tracer))
+}
+
+// Blocks until GDB connects, the ROM starts halted
+fn wait_gdb(port: u16) -> io::Result<GdbStub> {
+    let mut stub = GdbStub::bind(port)?;
+    println!("Waiting for GDB on localhost:{}", stub.port()?);
+    stub.accept()?;
+
+    Ok(stub)
+}
+
+// Prints the next instruction and waits for Enter
+fn wait_debugger(my_chip8: &Chip8) {
+    let pc = my_chip8.pc();
+    println!(
+        "pc: {:03X} - {:02X}{:02X}, v: {:02X?}",
+        pc,
+        my_chip8.peek(pc),
+        my_chip8.peek(pc + 1),
+        my_chip8.v()
+    );
+
+    let mut buffer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut buffer)
+        .expect("Failed to read line");
+}
+
+fn capture(
+    recorder: &mut Option<Recorder>,
+    my_chip8: &Chip8,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match recorder {
+        Some(r) => r.capture(my_chip8.screen(), my_chip8.is_beeping()),
+        None => Ok(()),
+    }
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Returns a message for the overlay
+fn take_screenshot(emu: &Chip8, palette: &Palette, scale: u32) -> String {
+    let filename = format!("screenshot-{}.png", timestamp());
+
+    match screenshot::save_png(&filename, emu.screen(), scale as usize, palette) {
+        Ok(()) => {
+            println!("Screenshot saved to {}", filename);
+            format!("Saved {}", filename)
+        }
+        Err(e) => {
+            eprintln!("Failed to save screenshot: {}", e);
+            "Screenshot failed".to_string()
+        }
+    }
+}
+
+fn finish_recording(recorder: Recorder) {
+    let frames = recorder.frames();
+    match recorder.finish() {
+        Ok(()) => println!("Recording finished, {} frames", frames),
+        Err(e) => eprintln!("Failed to finish recording: {}", e),
+    }
+}
+
+// Starts a GIF recording with sound, or finishes the running one
+fn toggle_recording(recorder: Option<Recorder>, palette: &Palette, scale: u32) -> Option<Recorder> {
+    match recorder {
+        Some(r) => {
+            finish_recording(r);
+            None
+        }
+        None => {
+            let filename = format!("recording-{}.gif", timestamp());
+            match Recorder::start(&filename, scale as usize, palette, true) {
+                Ok(r) => {
+                    println!("Recording to {}", filename);
+                    Some(r)
+                }
+                Err(e) => {
+                    eprintln!("Failed to start recording: {}", e);
+                    None
+                }
+            }
+        }
+    }
+}
+
+fn key_press(code: Keycode, emu: &mut Chip8, keymap: &[(Keycode, usize)]) {
+    if let Some(key) = chip8_key(code, keymap) {
+        emu.set_key(key, true);
+    }
+}
+
+fn key_release(code: Keycode, emu: &mut Chip8, keymap: &[(Keycode, usize)]) {
+    if let Some(key) = chip8_key(code, keymap) {
+        emu.set_key(key, false);
+    }
+}
+
+// Edits and runs cheat console commands, game keys don't reach the machine
+// while it's open. Returns false when it's closed.
+fn console_event(
+    console: &mut Console,
+    event: &Event,
+    my_chip8: &mut Chip8,
+    cheats: &mut Cheats,
+    file: &Path,
+    display: &mut Display,
+) -> bool {
+    match event {
+        Event::TextInput { text, .. } => console.type_text(text),
+        Event::KeyDown {
+            keycode: Some(Keycode::Escape | Keycode::F4),
+            ..
+        } => return false,
+        Event::KeyDown {
+            keycode: Some(Keycode::Backspace),
+            ..
+        } => console.backspace(),
+        Event::KeyDown {
+            keycode: Some(Keycode::Return | Keycode::KpEnter),
+            ..
+        } => {
+            let message = console.enter(my_chip8, cheats, file);
+            if !message.is_empty() {
+                display.overlay_mut().show_message(&message, Instant::now());
+            }
+        }
+        _ => {}
+    }
+
+    display.overlay_mut().set_prompt(Some(console.prompt()));
+    true
+}
+
+// The key of the ROM's keymap, or else of the default layout
+fn chip8_key(code: Keycode, keymap: &[(Keycode, usize)]) -> Option<usize> {
+    keymap
+        .iter()
+        .find(|(k, _)| *k == code)
+        .map(|(_, key)| *key)
+        .or_else(|| reg_keycode(code))
+}
+
 fn reg_keycode(keycode: Keycode) -> Option<usize> {
     match keycode {
         Keycode::X => Some(0x0),
@@ -108,26 +1140,3 @@ fn reg_keycode(keycode: Keycode) -> Option<usize> {
         _ => None,
     }
 }
-
-fn draw(canvas: &mut WindowCanvas, pixels: &[u8]) {
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
-    canvas.clear();
-
-    for col in 0..SCREEN_WIDTH {
-        let x: i32 = (col as u32 * SCALE) as i32;
-        for row in 0..SCREEN_HEIGHT {
-            let y: i32 = (row as u32 * SCALE) as i32;
-
-            if pixels[col + row * SCREEN_WIDTH] == 0 {
-                // Unset
-                canvas.set_draw_color(Color::RGB(0, 0, 0));
-            } else {
-                // Set
-                canvas.set_draw_color(Color::RGB(255, 255, 255));
-            }
-
-            canvas.fill_rect(Rect::new(x, y, SCALE, SCALE)).unwrap();
-        }
-    }
-    canvas.present();
-}
@@ -0,0 +1,377 @@
+// Memory viewer in a second window, opened with F2: a live hex dump of the
+// 4 KiB memory with ASCII and sprite columns, the bytes at pc and I
+// highlighted. Bytes can be edited with the hex keys while paused.
+use crate::display;
+use crate::overlay::{GLYPH_HEIGHT, GLYPH_WIDTH};
+use chip8_core::chip8::{MEM_SIZE, PC_START};
+use chip8_core::palette::Palette;
+use chip8_core::Chip8;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+use sdl2::VideoSubsystem;
+
+// Bytes per line, each line also shows them as an 8 x 8 sprite
+pub const ROW_BYTES: usize = 8;
+
+// Side of a font pixel
+const FONT_SIZE: i32 = 2;
+
+// Lines shown when the window opens
+const ROWS: usize = 32;
+
+// Characters before the bytes ("0200: "), before the ASCII column and
+// before the sprite column
+const BYTES_COLUMN: usize = 6;
+const ASCII_COLUMN: usize = BYTES_COLUMN + ROW_BYTES * 3 + 1;
+const SPRITE_COLUMN: usize = ASCII_COLUMN + ROW_BYTES + 2;
+
+// Backdrops of the bytes at pc and I
+const PC_COLOR: [u8; 3] = [0xA0, 0x20, 0x20];
+const I_COLOR: [u8; 3] = [0x20, 0x40, 0xA0];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Highlight {
+    None,
+    Cursor,
+
+    // One of the two bytes of the instruction at pc
+    Pc,
+    I,
+}
+
+pub struct Row {
+    pub addr: usize,
+    pub bytes: [u8; ROW_BYTES],
+}
+
+impl Row {
+    // Printable ASCII, anything else as .
+    pub fn ascii(&self) -> String {
+        self.bytes
+            .iter()
+            .map(|b| match b {
+                0x20..=0x7E => *b as char,
+                _ => '.',
+            })
+            .collect()
+    }
+}
+
+pub struct MemoryView {
+    cursor: usize,
+
+    // Address of the first line shown
+    top: usize,
+
+    // First hex digit typed over the byte at the cursor
+    high_nibble: Option<u8>,
+}
+
+impl MemoryView {
+    pub fn new() -> MemoryView {
+        MemoryView {
+            cursor: PC_START,
+            top: PC_START,
+            high_nibble: None,
+        }
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // Moves the cursor by delta bytes, stopping at the ends of memory.
+    // A half typed byte is dropped.
+    pub fn move_by(&mut self, delta: isize) {
+        let last = MEM_SIZE as isize - 1;
+        self.cursor = (self.cursor as isize + delta).clamp(0, last) as usize;
+        self.high_nibble = None;
+    }
+
+    // Two digits make a byte, written at the cursor which then moves on
+    pub fn type_digit(&mut self, chip8: &mut Chip8, digit: u8) {
+        match self.high_nibble.take() {
+            None => self.high_nibble = Some(digit),
+            Some(high) => {
+                chip8.poke(self.cursor, high << 4 | digit);
+                self.move_by(1);
+            }
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        self.high_nibble = None;
+    }
+
+    // Lines fitting in rows, scrolled to keep the cursor visible
+    pub fn rows(&mut self, chip8: &Chip8, rows: usize) -> Vec<Row> {
+        let rows = rows.max(1);
+        let cursor_row = self.cursor - self.cursor % ROW_BYTES;
+        if cursor_row < self.top {
+            self.top = cursor_row;
+        } else if cursor_row >= self.top + rows * ROW_BYTES {
+            self.top = cursor_row + ROW_BYTES - rows * ROW_BYTES;
+        }
+        self.top = self.top.min(MEM_SIZE.saturating_sub(rows * ROW_BYTES));
+
+        (self.top..MEM_SIZE)
+            .step_by(ROW_BYTES)
+            .take(rows)
+            .map(|addr| {
+                let mut bytes = [0; ROW_BYTES];
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = chip8.peek(addr + i);
+                }
+                Row { addr, bytes }
+            })
+            .collect()
+    }
+
+    pub fn highlight(&self, chip8: &Chip8, addr: usize) -> Highlight {
+        if addr == self.cursor {
+            Highlight::Cursor
+        } else if addr == chip8.pc() || addr == chip8.pc() + 1 {
+            Highlight::Pc
+        } else if addr == chip8.i() {
+            Highlight::I
+        } else {
+            Highlight::None
+        }
+    }
+
+    // Hex of the byte, the typed digit and _ while editing it
+    pub fn byte_text(&self, addr: usize, value: u8) -> String {
+        match self.high_nibble {
+            Some(high) if addr == self.cursor => format!("{:X}_", high),
+            _ => format!("{:02X}", value),
+        }
+    }
+}
+
+pub struct MemoryWindow {
+    canvas: WindowCanvas,
+    view: MemoryView,
+
+    // Lines shown at the last draw, for Page Up and Page Down
+    rows: usize,
+}
+
+impl MemoryWindow {
+    pub fn open(video: &VideoSubsystem) -> Result<MemoryWindow, String> {
+        let line_height = (GLYPH_HEIGHT as i32 + 3) * FONT_SIZE;
+        let width = (SPRITE_COLUMN * (GLYPH_WIDTH + 1) + ROW_BYTES + 4) as i32 * FONT_SIZE;
+        let height = ROWS as i32 * line_height + 4 * FONT_SIZE;
+
+        let window = video
+            .window("Memory", width as u32, height as u32)
+            .resizable()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+
+        Ok(MemoryWindow {
+            canvas,
+            view: MemoryView::new(),
+            rows: ROWS,
+        })
+    }
+
+    // Whether the event is for this window
+    pub fn owns(&self, event: &Event) -> bool {
+        event.get_window_id() == Some(self.canvas.window().id())
+    }
+
+    // Handles an event of this window, editing only while paused.
+    // Returns false when the window is closed.
+    pub fn handle(&mut self, event: &Event, chip8: &mut Chip8, paused: bool) -> bool {
+        let keycode = match event {
+            Event::Window {
+                win_event: WindowEvent::Close,
+                ..
+            } => return false,
+            Event::KeyDown {
+                keycode: Some(k), ..
+            } => *k,
+            _ => return true,
+        };
+
+        let page = (self.rows * ROW_BYTES) as isize;
+        match keycode {
+            Keycode::F2 => return false,
+            Keycode::Left => self.view.move_by(-1),
+            Keycode::Right => self.view.move_by(1),
+            Keycode::Up => self.view.move_by(-(ROW_BYTES as isize)),
+            Keycode::Down => self.view.move_by(ROW_BYTES as isize),
+            Keycode::PageUp => self.view.move_by(-page),
+            Keycode::PageDown => self.view.move_by(page),
+            Keycode::Home => self.view.move_by(-(MEM_SIZE as isize)),
+            Keycode::End => self.view.move_by(MEM_SIZE as isize),
+            Keycode::Escape => self.view.cancel(),
+            k => {
+                if let Some(digit) = hex_digit(k).filter(|_| paused) {
+                    self.view.type_digit(chip8, digit);
+                }
+            }
+        }
+
+        true
+    }
+
+    pub fn draw(&mut self, chip8: &Chip8, palette: &Palette, paused: bool) -> Result<(), String> {
+        let (_, height) = self.canvas.output_size()?;
+        let line_height = (GLYPH_HEIGHT as i32 + 3) * FONT_SIZE;
+        self.rows = ((height as i32 - 2 * FONT_SIZE) / line_height).max(1) as usize;
+        let rows = self.view.rows(chip8, self.rows);
+
+        let [r, g, b] = palette.off;
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+        self.canvas.set_blend_mode(BlendMode::Blend);
+
+        let normal = [palette.on, palette.off];
+        let column = |chars: usize| 2 * FONT_SIZE + (chars * (GLYPH_WIDTH + 1)) as i32 * FONT_SIZE;
+        for (line, row) in rows.iter().enumerate() {
+            let y = 2 * FONT_SIZE + line as i32 * line_height;
+            let addr = format!("{:04X}:", row.addr);
+            display::draw_text(&mut self.canvas, &addr, column(0), y, FONT_SIZE, normal)?;
+
+            for (i, value) in row.bytes.iter().enumerate() {
+                let addr = row.addr + i;
+                let colors = match self.view.highlight(chip8, addr) {
+                    Highlight::None => normal,
+                    Highlight::Cursor => [palette.off, palette.on],
+                    Highlight::Pc => [palette.on, PC_COLOR],
+                    Highlight::I => [palette.on, I_COLOR],
+                };
+                let text = self.view.byte_text(addr, *value);
+                let x = column(BYTES_COLUMN + i * 3);
+                display::draw_text(&mut self.canvas, &text, x, y, FONT_SIZE, colors)?;
+            }
+
+            let x = column(ASCII_COLUMN);
+            display::draw_text(&mut self.canvas, &row.ascii(), x, y, FONT_SIZE, normal)?;
+
+            // Each byte is a line of the sprite, like DXYN draws it
+            let x = column(SPRITE_COLUMN);
+            let mut pixels = Vec::new();
+            for (line, byte) in row.bytes.iter().enumerate() {
+                for bit in 0..8 {
+                    if byte & (0x80 >> bit) != 0 {
+                        pixels.push(Rect::new(
+                            x + bit * FONT_SIZE,
+                            y - FONT_SIZE + line as i32 * FONT_SIZE,
+                            FONT_SIZE as u32,
+                            FONT_SIZE as u32,
+                        ));
+                    }
+                }
+            }
+            let [r, g, b] = palette.on;
+            self.canvas.set_draw_color(Color::RGB(r, g, b));
+            self.canvas.fill_rects(&pixels)?;
+        }
+
+        let mut title = format!(
+            "Memory - cursor {:03X}, pc {:03X}, I {:03X}",
+            self.view.cursor(),
+            chip8.pc(),
+            chip8.i()
+        );
+        if !paused {
+            title.push_str(" (pause to edit)");
+        }
+        if self.canvas.window().title() != title {
+            self.canvas
+                .window_mut()
+                .set_title(&title)
+                .map_err(|e| e.to_string())?;
+        }
+
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+fn hex_digit(keycode: Keycode) -> Option<u8> {
+    let digit = match keycode {
+        Keycode::Num0 | Keycode::Kp0 => 0x0,
+        Keycode::Num1 | Keycode::Kp1 => 0x1,
+        Keycode::Num2 | Keycode::Kp2 => 0x2,
+        Keycode::Num3 | Keycode::Kp3 => 0x3,
+        Keycode::Num4 | Keycode::Kp4 => 0x4,
+        Keycode::Num5 | Keycode::Kp5 => 0x5,
+        Keycode::Num6 | Keycode::Kp6 => 0x6,
+        Keycode::Num7 | Keycode::Kp7 => 0x7,
+        Keycode::Num8 | Keycode::Kp8 => 0x8,
+        Keycode::Num9 | Keycode::Kp9 => 0x9,
+        Keycode::A => 0xA,
+        Keycode::B => 0xB,
+        Keycode::C => 0xC,
+        Keycode::D => 0xD,
+        Keycode::E => 0xE,
+        Keycode::F => 0xF,
+        _ => return None,
+    };
+
+    Some(digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit() {
+        let mut chip8 = Chip8::init();
+        let mut view = MemoryView::new();
+
+        view.type_digit(&mut chip8, 0xA);
+        assert_eq!(view.byte_text(PC_START, 0), "A_");
+        view.type_digit(&mut chip8, 0x5);
+        assert_eq!(chip8.peek(PC_START), 0xA5);
+        assert_eq!(view.cursor(), PC_START + 1);
+
+        // Moving drops the half typed byte
+        view.type_digit(&mut chip8, 0x1);
+        view.move_by(-1);
+        assert_eq!(view.byte_text(PC_START, 0xA5), "A5");
+        view.move_by(-(MEM_SIZE as isize));
+        assert_eq!(view.cursor(), 0);
+    }
+
+    #[test]
+    fn test_rows() {
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(b"HI\x00\xFF").unwrap();
+        chip8.set_i(PC_START + 3);
+        let mut view = MemoryView::new();
+
+        let rows = view.rows(&chip8, 4);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0].addr, PC_START);
+        assert_eq!(rows[0].ascii(), "HI......");
+        assert_eq!(view.highlight(&chip8, PC_START), Highlight::Cursor);
+        assert_eq!(view.highlight(&chip8, PC_START + 1), Highlight::Pc);
+        assert_eq!(view.highlight(&chip8, PC_START + 3), Highlight::I);
+        assert_eq!(view.highlight(&chip8, PC_START + 4), Highlight::None);
+
+        // Scrolls to the cursor, the last lines stay full
+        view.move_by(MEM_SIZE as isize);
+        let rows = view.rows(&chip8, 4);
+        assert_eq!(rows[3].addr, MEM_SIZE - ROW_BYTES);
+        view.move_by(-(MEM_SIZE as isize));
+        assert_eq!(view.rows(&chip8, 4)[0].addr, 0);
+    }
+
+    #[test]
+    fn test_hex_digit() {
+        assert_eq!(hex_digit(Keycode::Num7), Some(7));
+        assert_eq!(hex_digit(Keycode::C), Some(0xC));
+        assert_eq!(hex_digit(Keycode::Kp9), Some(9));
+        assert_eq!(hex_digit(Keycode::G), None);
+        assert_eq!(hex_digit(Keycode::Left), None);
+    }
+}
@@ -0,0 +1,268 @@
+// Text drawn over the CHIP-8 screen: a message shown for a few seconds,
+// FPS and IPS counters, the paused indicator and the cheat console prompt. Text uses an embedded
+// 3x5 bitmap font with uppercase letters, digits and some punctuation.
+use std::time::{Duration, Instant};
+
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+
+// Glyphs are one pixel apart
+const ADVANCE: usize = GLYPH_WIDTH + 1;
+
+// How long a message stays on screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+
+// How often the counters are refreshed
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+// Rows of the glyph, most significant of the 3 bits on the left.
+// Lowercase letters use the uppercase glyphs, unknown characters show as ?.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0, 0, 0, 0, 0],
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 2, 2],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        '.' => [0, 0, 0, 0, 2],
+        ',' => [0, 0, 0, 2, 4],
+        ':' => [0, 2, 0, 2, 0],
+        '-' => [0, 0, 7, 0, 0],
+        '+' => [0, 2, 7, 2, 0],
+        '=' => [0, 7, 0, 7, 0],
+        '/' => [1, 1, 2, 4, 4],
+        '(' => [1, 2, 2, 2, 1],
+        ')' => [4, 2, 2, 2, 4],
+        '%' => [5, 1, 2, 4, 5],
+        '!' => [2, 2, 2, 0, 2],
+        '_' => [0, 0, 0, 0, 7],
+        '\'' => [2, 2, 0, 0, 0],
+        '>' => [4, 2, 1, 2, 4],
+        '<' => [1, 2, 4, 2, 1],
+        _ => [6, 1, 2, 0, 2],
+    }
+}
+
+// Width of the text in font pixels
+pub fn text_width(text: &str) -> usize {
+    (text.chars().count() * ADVANCE).saturating_sub(1)
+}
+
+// Lit pixels of the text in font pixels, from its top left corner
+pub fn text_pixels(text: &str) -> Vec<(usize, usize)> {
+    let mut pixels = Vec::new();
+
+    for (i, c) in text.chars().enumerate() {
+        for (y, row) in glyph(c).iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (0b100 >> x) != 0 {
+                    pixels.push((i * ADVANCE + x, y));
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+pub struct Overlay {
+    // Shown in the top left corner until it expires
+    message: Option<(String, Instant)>,
+
+    paused: bool,
+
+    // Command line of the cheat console, under the message
+    prompt: Option<String>,
+
+    // Counters shown in the top right corner
+    show_stats: bool,
+    stats: String,
+
+    // Frames and instructions counted since stats_start
+    stats_start: Instant,
+    frames: u32,
+    instructions: u64,
+
+    // Whether the text changed since the last take_changed()
+    changed: bool,
+}
+
+impl Overlay {
+    pub fn new(now: Instant) -> Overlay {
+        Overlay {
+            message: None,
+            paused: false,
+            prompt: None,
+            show_stats: false,
+            stats: String::new(),
+            stats_start: now,
+            frames: 0,
+            instructions: 0,
+            changed: false,
+        }
+    }
+
+    pub fn show_message(&mut self, text: &str, now: Instant) {
+        self.message = Some((text.to_string(), now + MESSAGE_DURATION));
+        self.changed = true;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.changed |= self.paused != paused;
+        self.paused = paused;
+    }
+
+    pub fn set_prompt(&mut self, prompt: Option<String>) {
+        self.changed |= self.prompt != prompt;
+        self.prompt = prompt;
+    }
+
+    pub fn toggle_stats(&mut self) {
+        self.show_stats = !self.show_stats;
+        self.changed = true;
+    }
+
+    // Counts a displayed frame and the instructions run for it
+    pub fn count_frame(&mut self, instructions: u32, now: Instant) {
+        self.frames += 1;
+        self.instructions += instructions as u64;
+
+        let elapsed = now - self.stats_start;
+        if elapsed >= STATS_INTERVAL {
+            let seconds = elapsed.as_secs_f64();
+            self.stats = format!(
+                "{:.0} FPS {:.0} IPS",
+                self.frames as f64 / seconds,
+                self.instructions as f64 / seconds
+            );
+            self.changed |= self.show_stats;
+
+            self.stats_start = now;
+            self.frames = 0;
+            self.instructions = 0;
+        }
+    }
+
+    // Removes the message once it expired
+    pub fn update(&mut self, now: Instant) {
+        if self.message.as_ref().is_some_and(|(_, end)| now >= *end) {
+            self.message = None;
+            self.changed = true;
+        }
+    }
+
+    // Whether the overlay must be drawn again, clearing the flag
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+        changed
+    }
+
+    // Lines aligned to the left of the screen, top to bottom
+    pub fn left(&self) -> Vec<&str> {
+        self.message
+            .iter()
+            .map(|(text, _)| text.as_str())
+            .chain(self.prompt.as_deref())
+            .collect()
+    }
+
+    // Lines aligned to the right of the screen, top to bottom
+    pub fn right(&self) -> Vec<&str> {
+        let mut lines = Vec::new();
+        if self.show_stats && !self.stats.is_empty() {
+            lines.push(self.stats.as_str());
+        }
+        if self.paused {
+            lines.push("PAUSED");
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_pixels() {
+        assert_eq!(text_width("HI"), 7);
+        assert_eq!(text_width(""), 0);
+
+        let pixels = text_pixels("1.");
+        assert_eq!(pixels.len(), 8 + 1);
+        assert!(pixels.contains(&(1, 0)));
+        assert!(pixels.contains(&(0, 1)));
+        assert!(pixels.contains(&(5, 4)));
+        assert!(!pixels.contains(&(4, 4)));
+        assert_eq!(text_pixels("a"), text_pixels("A"));
+    }
+
+    #[test]
+    fn test_message_expires() {
+        let start = Instant::now();
+        let mut overlay = Overlay::new(start);
+        assert!(!overlay.take_changed());
+
+        overlay.show_message("Screenshot saved", start);
+        assert!(overlay.take_changed());
+        assert_eq!(overlay.left(), ["Screenshot saved"]);
+
+        overlay.update(start + Duration::from_secs(1));
+        assert!(!overlay.take_changed());
+        overlay.update(start + MESSAGE_DURATION);
+        assert!(overlay.take_changed());
+        assert!(overlay.left().is_empty());
+
+        overlay.set_prompt(Some("> _".to_string()));
+        assert!(overlay.take_changed());
+        assert_eq!(overlay.left(), ["> _"]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let start = Instant::now();
+        let mut overlay = Overlay::new(start);
+        overlay.toggle_stats();
+        overlay.set_paused(true);
+
+        for frame in 1..=60 {
+            overlay.count_frame(10, start + STATS_INTERVAL * frame / 60);
+        }
+
+        assert_eq!(overlay.right(), ["60 FPS 600 IPS", "PAUSED"]);
+    }
+}
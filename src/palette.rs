@@ -0,0 +1,115 @@
+#[cfg(feature = "alloc")]
+use alloc::{format, string::String};
+#[cfg(feature = "alloc")]
+use core::str::FromStr;
+
+// Colors used to render the monochrome framebuffer, as [r, g, b]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    // Unset pixel
+    pub off: [u8; 3],
+
+    // Set pixel
+    pub on: [u8; 3],
+}
+
+impl Palette {
+    pub const DEFAULT: Palette = Palette {
+        off: [0x00, 0x00, 0x00],
+        on: [0xFF, 0xFF, 0xFF],
+    };
+
+    pub const AMBER: Palette = Palette {
+        off: [0x1A, 0x0F, 0x00],
+        on: [0xFF, 0xB0, 0x00],
+    };
+
+    pub const GREEN: Palette = Palette {
+        off: [0x00, 0x1A, 0x00],
+        on: [0x33, 0xFF, 0x33],
+    };
+
+    pub const LCD: Palette = Palette {
+        off: [0x9B, 0xBC, 0x0F],
+        on: [0x0F, 0x38, 0x0F],
+    };
+
+    // Color of a pixel value from the framebuffer
+    pub fn color(&self, pixel: u8) -> [u8; 3] {
+        if pixel == 0 {
+            self.off
+        } else {
+            self.on
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::DEFAULT
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl FromStr for Palette {
+    type Err = String;
+
+    // Parses a preset name or two hex colors "RRGGBB,RRGGBB" (off, on)
+    fn from_str(s: &str) -> Result<Palette, String> {
+        match s {
+            "default" => Ok(Palette::DEFAULT),
+            "amber" => Ok(Palette::AMBER),
+            "green" => Ok(Palette::GREEN),
+            "lcd" => Ok(Palette::LCD),
+            _ => {
+                let mut colors = s.split(',').map(parse_hex_color);
+
+                match (colors.next(), colors.next(), colors.next()) {
+                    (Some(Some(off)), Some(Some(on)), None) => Ok(Palette { off, on }),
+                    _ => Err(format!("Unknown palette: {}", s)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn parse_hex_color(s: &str) -> Option<[u8; 3]> {
+    let s = s.trim_start_matches('#');
+
+    if s.len() != 6 || !s.is_ascii() {
+        return None;
+    }
+
+    let mut color = [0; 3];
+    for (i, c) in color.iter_mut().enumerate() {
+        *c = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Some(color)
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_preset() {
+        assert_eq!("amber".parse::<Palette>(), Ok(Palette::AMBER));
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        let palette = "#102030,A0b0C0".parse::<Palette>().unwrap();
+
+        assert_eq!(palette.off, [0x10, 0x20, 0x30]);
+        assert_eq!(palette.on, [0xA0, 0xB0, 0xC0]);
+    }
+
+    #[test]
+    fn test_parse_unknown() {
+        assert!("102030".parse::<Palette>().is_err());
+        assert!("102030,10203".parse::<Palette>().is_err());
+        assert!("purple".parse::<Palette>().is_err());
+    }
+}
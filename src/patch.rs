@@ -0,0 +1,400 @@
+// ROM patches, as fan translations and fixes are distributed: IPS, and
+// BPS whose CRC32s of the source ROM, the patched ROM and the patch itself
+// are all checked.
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+
+// Source, target and patch CRC32s at the end of a BPS patch
+const BPS_FOOTER_SIZE: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PatchError {
+    // Neither IPS nor BPS
+    UnknownFormat,
+
+    // Ends in the middle of a record or action
+    Truncated,
+
+    // The BPS patch was made for another ROM, size or CRC32 differ
+    WrongRom,
+
+    // A BPS action reads or writes outside of the ROMs
+    OutOfBounds,
+
+    // Bad CRC32 of the patched ROM or of the patch
+    BadChecksum,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "Not an IPS or BPS patch"),
+            PatchError::Truncated => write!(f, "Patch is truncated"),
+            PatchError::WrongRom => write!(f, "Patch is for another ROM"),
+            PatchError::OutOfBounds => write!(f, "Patch reads or writes out of bounds"),
+            PatchError::BadChecksum => write!(f, "Patch is corrupted, bad checksum"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for PatchError {}
+
+// Applies an IPS or BPS patch, told apart by their header
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+// Patch next to the ROM with its name, game.bps or else game.ips for
+// game.ch8
+#[cfg(feature = "std")]
+pub fn find(rom: &Path) -> Option<PathBuf> {
+    ["bps", "ips"]
+        .iter()
+        .map(|extension| rom.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+// Records of a 3 byte offset, a 2 byte size and the data, or a zero size,
+// a 2 byte count and a byte to repeat. After EOF an optional 3 byte size
+// truncates the ROM.
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut out = rom.to_vec();
+    let mut r = Reader {
+        patch,
+        pos: IPS_MAGIC.len(),
+    };
+
+    loop {
+        let record = r.bytes(3)?;
+        if record == IPS_EOF {
+            break;
+        }
+
+        let offset = be(record);
+        let size = be(r.bytes(2)?);
+        let (size, data) = if size == 0 {
+            let count = be(r.bytes(2)?);
+            (count, None)
+        } else {
+            (size, Some(r.bytes(size)?))
+        };
+
+        if out.len() < offset + size {
+            out.resize(offset + size, 0);
+        }
+        match data {
+            Some(data) => out[offset..offset + size].copy_from_slice(data),
+            None => out[offset..offset + size].fill(r.bytes(1)?[0]),
+        }
+    }
+
+    if let Ok(size) = r.bytes(3) {
+        out.truncate(be(size));
+    }
+
+    Ok(out)
+}
+
+// Header with the sizes of the source and target ROMs and of metadata,
+// then actions building the target from the source, the patch or itself
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE {
+        return Err(PatchError::Truncated);
+    }
+
+    let actions_end = patch.len() - BPS_FOOTER_SIZE;
+    let footer = &patch[actions_end..];
+    let source_crc = le(&footer[0..4]);
+    let target_crc = le(&footer[4..8]);
+    let patch_crc = le(&footer[8..12]);
+    if crc32(&patch[..patch.len() - 4]) != patch_crc {
+        return Err(PatchError::BadChecksum);
+    }
+
+    let mut r = Reader {
+        patch: &patch[..actions_end],
+        pos: BPS_MAGIC.len(),
+    };
+    let source_size = r.number()?;
+    let target_size = r.number()?;
+    let metadata_size = r.number()?;
+    r.bytes(metadata_size)?;
+
+    if source_size != rom.len() || crc32(rom) != source_crc {
+        return Err(PatchError::WrongRom);
+    }
+
+    // Not allocated up front, the header could ask for any size
+    let mut out = Vec::new();
+    let mut source_offset = 0;
+    let mut target_offset = 0;
+    while r.pos < actions_end {
+        let action = r.number()?;
+        let length = (action >> 2) + 1;
+        if length > target_size - out.len() {
+            return Err(PatchError::OutOfBounds);
+        }
+
+        match action & 3 {
+            // SourceRead, at the same offset in the source
+            0 => {
+                let data = rom.get(out.len()..out.len() + length);
+                out.extend_from_slice(data.ok_or(PatchError::OutOfBounds)?);
+            }
+            // TargetRead, from the patch
+            1 => out.extend_from_slice(r.bytes(length)?),
+            // SourceCopy, from anywhere in the source
+            2 => {
+                source_offset = r.offset(source_offset)?;
+                let data = source_offset
+                    .checked_add(length)
+                    .and_then(|end| rom.get(source_offset..end));
+                out.extend_from_slice(data.ok_or(PatchError::OutOfBounds)?);
+                source_offset += length;
+            }
+            // TargetCopy, from what's already written, the copy can overlap
+            _ => {
+                target_offset = r.offset(target_offset)?;
+                if target_offset >= out.len() {
+                    return Err(PatchError::OutOfBounds);
+                }
+                for _ in 0..length {
+                    out.push(out[target_offset]);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if out.len() != target_size || crc32(&out) != target_crc {
+        return Err(PatchError::BadChecksum);
+    }
+
+    Ok(out)
+}
+
+// Sequential reads from a patch
+struct Reader<'a> {
+    patch: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.patch.get(self.pos..end))
+            .ok_or(PatchError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    // BPS variable length number, 7 bits per byte, the last one has the
+    // high bit set
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut number: usize = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let byte = self.bytes(1)?[0] as usize;
+            number = (byte & 0x7F)
+                .checked_mul(shift)
+                .and_then(|n| n.checked_add(number))
+                .ok_or(PatchError::OutOfBounds)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+
+            shift = shift.checked_mul(0x80).ok_or(PatchError::OutOfBounds)?;
+            number = number.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+        }
+    }
+
+    // BPS copy offset relative to the previous one, the low bit is the sign
+    fn offset(&mut self, previous: usize) -> Result<usize, PatchError> {
+        let number = self.number()?;
+        let delta = number >> 1;
+
+        if number & 1 == 0 {
+            previous.checked_add(delta)
+        } else {
+            previous.checked_sub(delta)
+        }
+        .ok_or(PatchError::OutOfBounds)
+    }
+}
+
+fn be(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |n, b| n << 8 | *b as usize)
+}
+
+fn le(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// CRC-32 as in zlib and PNG
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(mut n: usize, out: &mut Vec<u8>) {
+        loop {
+            let byte = (n & 0x7F) as u8;
+            n >>= 7;
+            if n == 0 {
+                out.push(0x80 | byte);
+                return;
+            }
+            out.push(byte);
+            n -= 1;
+        }
+    }
+
+    // BPS patch from source to target with the given actions
+    fn bps(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        number(source.len(), &mut patch);
+        number(target.len(), &mut patch);
+        number(0, &mut patch);
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    // Action with its length, and its offset for copies
+    fn action(kind: usize, length: usize, out: &mut Vec<u8>) {
+        number((length - 1) << 2 | kind, out);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn test_ips() {
+        let rom = [0x00, 0xE0, 0x12, 0x00];
+        let mut patch = IPS_MAGIC.to_vec();
+        // 2 bytes at 2
+        patch.extend_from_slice(&[0, 0, 2, 0, 2, 0x12, 0x02]);
+        // 3 times 0xAA at 5, past the end
+        patch.extend_from_slice(&[0, 0, 5, 0, 0, 0, 3, 0xAA]);
+        patch.extend_from_slice(IPS_EOF);
+
+        assert_eq!(
+            apply(&rom, &patch),
+            Ok(vec![0x00, 0xE0, 0x12, 0x02, 0x00, 0xAA, 0xAA, 0xAA])
+        );
+
+        // Truncation after EOF
+        patch.extend_from_slice(&[0, 0, 2]);
+        assert_eq!(apply(&rom, &patch), Ok(vec![0x00, 0xE0]));
+
+        assert_eq!(
+            apply(&rom, &patch[..patch.len() - 6]),
+            Err(PatchError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = b"CHIP-8 GAME";
+        let target = b"CHIP-8 JEU JEU!!!!";
+        let mut actions = Vec::new();
+        // "CHIP-8 " from the source
+        action(0, 7, &mut actions);
+        // "JEU " from the patch
+        action(1, 4, &mut actions);
+        actions.extend_from_slice(b"JEU ");
+        // "JEU" again from the target at 7
+        action(3, 3, &mut actions);
+        number(7 << 1, &mut actions);
+        // "!!!!", copying each "!" written just before, from 14 after
+        // the previous copy ended at 10
+        action(1, 1, &mut actions);
+        actions.push(b'!');
+        action(3, 3, &mut actions);
+        number((14 - 10) << 1, &mut actions);
+        let patch = bps(source, target, &actions);
+
+        assert_eq!(apply(source, &patch).as_deref(), Ok(&target[..]));
+
+        assert_eq!(apply(b"CHIP-8 GAMF", &patch), Err(PatchError::WrongRom));
+        let mut corrupted = patch.clone();
+        corrupted[8] ^= 1;
+        assert_eq!(apply(source, &corrupted), Err(PatchError::BadChecksum));
+        assert_eq!(apply(source, b"NOPE"), Err(PatchError::UnknownFormat));
+    }
+
+    #[test]
+    fn test_bps_bad_header() {
+        let source = b"ABCDEF";
+
+        // Metadata as long as memory can address
+        let mut patch = BPS_MAGIC.to_vec();
+        number(source.len(), &mut patch);
+        number(source.len(), &mut patch);
+        number(usize::MAX, &mut patch);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        assert_eq!(apply(source, &patch), Err(PatchError::Truncated));
+
+        // A source copy from far past the end of the source
+        let mut actions = Vec::new();
+        action(2, 2, &mut actions);
+        number((usize::MAX >> 1) << 1, &mut actions);
+        let patch = bps(source, b"AB", &actions);
+        assert_eq!(apply(source, &patch), Err(PatchError::OutOfBounds));
+    }
+
+    #[test]
+    fn test_bps_source_copy() {
+        let source = b"ABCDEF";
+        let target = b"EFAB";
+        let mut actions = Vec::new();
+        action(2, 2, &mut actions);
+        number(4 << 1, &mut actions);
+        // Back from 6 to 0
+        action(2, 2, &mut actions);
+        number(6 << 1 | 1, &mut actions);
+        let patch = bps(source, target, &actions);
+
+        assert_eq!(apply(source, &patch).as_deref(), Ok(&target[..]));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::chip8::STACK_SIZE;
+#[cfg(feature = "alloc")]
+use alloc::{format, string::String};
+use core::fmt;
+#[cfg(feature = "alloc")]
+use core::str::FromStr;
+
+// Machine the ROM was written for.
+// Only the CHIP-8 instruction set is emulated, the platform picks the
+// quirks that differ between interpreters.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Platform {
+    // Original COSMAC VIP interpreter
+    #[default]
+    Chip8,
+
+    // SUPER-CHIP 1.1 on the HP48
+    Schip,
+
+    // XO-CHIP (Octo)
+    XoChip,
+}
+
+impl Platform {
+    // Quirks of the original interpreter of the platform
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::VIP,
+            Platform::Schip => Quirks::SCHIP,
+            Platform::XoChip => Quirks::XOCHIP,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Platform, String> {
+        match s {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" => Ok(Platform::Schip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!("Unknown platform: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Platform::Chip8 => "chip8",
+            Platform::Schip => "schip",
+            Platform::XoChip => "xochip",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+// Behaviours that differ between CHIP-8 interpreters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift vY into vX, instead of shifting vX in place
+    pub shift_uses_vy: bool,
+
+    // FX55/FX65 leave addr_reg pointing after the last register
+    pub load_store_increments_i: bool,
+
+    // BXNN jumps to vX + XNN, instead of BNNN jumping to v0 + NNN
+    pub jump_uses_vx: bool,
+
+    // 8XY1/8XY2/8XY3 reset vF to 0
+    pub vf_reset: bool,
+
+    // DXYN clips sprites at the screen edges, instead of wrapping around
+    pub clip_sprites: bool,
+
+    // Return addresses 2NNN can push before the stack overflows,
+    // at most STACK_SIZE
+    pub stack_depth: usize,
+
+    // Keep the stack in emulated memory at STACK_ADDR, like the VIP did,
+    // so ROMs can read and overwrite return addresses
+    pub stack_in_memory: bool,
+
+    // Lay out memory like the VIP: the stack at STACK_ADDR, the display
+    // at DISPLAY_ADDR and the font at VIP_FONT_ADDR. Below PC_START is the
+    // interpreter's, instructions can't write there, and ROMs must end
+    // before the stack. Implies stack_in_memory.
+    pub vip_memory_map: bool,
+}
+
+impl Quirks {
+    // Behaviour of this emulator before quirks were configurable
+    pub const DEFAULT: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        stack_depth: STACK_SIZE,
+        stack_in_memory: false,
+        vip_memory_map: false,
+    };
+
+    pub const VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        vf_reset: true,
+        clip_sprites: true,
+        stack_depth: 12,
+        stack_in_memory: false,
+        vip_memory_map: false,
+    };
+
+    pub const SCHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        stack_depth: 16,
+        stack_in_memory: false,
+        vip_memory_map: false,
+    };
+
+    pub const XOCHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increments_i: true,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        stack_depth: 16,
+        stack_in_memory: false,
+        vip_memory_map: false,
+    };
+}
+
+impl Quirks {
+    // Packs the flags into one byte, e.g. for save states.
+    // stack_depth isn't included.
+    pub fn to_bits(self) -> u8 {
+        self.shift_uses_vy as u8
+            | (self.load_store_increments_i as u8) << 1
+            | (self.jump_uses_vx as u8) << 2
+            | (self.vf_reset as u8) << 3
+            | (self.clip_sprites as u8) << 4
+            | (self.stack_in_memory as u8) << 5
+            | (self.vip_memory_map as u8) << 6
+    }
+
+    pub fn from_bits(bits: u8, stack_depth: usize) -> Quirks {
+        Quirks {
+            shift_uses_vy: bits & 0x01 != 0,
+            load_store_increments_i: bits & 0x02 != 0,
+            jump_uses_vx: bits & 0x04 != 0,
+            vf_reset: bits & 0x08 != 0,
+            clip_sprites: bits & 0x10 != 0,
+            stack_depth,
+            stack_in_memory: bits & 0x20 != 0,
+            vip_memory_map: bits & 0x40 != 0,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::DEFAULT
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl FromStr for Quirks {
+    type Err = String;
+
+    // Parses the name of a quirks preset
+    fn from_str(s: &str) -> Result<Quirks, String> {
+        match s {
+            "default" => Ok(Quirks::DEFAULT),
+            "vip" | "chip8" => Ok(Quirks::VIP),
+            "schip" => Ok(Quirks::SCHIP),
+            "xochip" => Ok(Quirks::XOCHIP),
+            _ => Err(format!("Unknown quirks preset: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+// Execution profiler, counting instructions per address, per instruction
+// type and per subroutine, DXYN draws per frame, and data reads per address.
+use crate::chip8::{Chip8, MEM_SIZE};
+use crate::disasm::Instruction;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+// Entries listed in each section of the report
+const REPORT_TOP: usize = 10;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Subroutine {
+    pub calls: u64,
+
+    // Instructions executed from the call to the return, including
+    // nested calls and the 2NNN and 00EE themselves
+    pub instructions: u64,
+}
+
+pub struct Profiler {
+    // Instructions executed, and the last opcode seen, per address
+    counts: Vec<u64>,
+    opcodes: Vec<usize>,
+
+    // Bytes read as data by DXYN and FX65, per address
+    reads: Vec<u64>,
+
+    mnemonics: HashMap<&'static str, u64>,
+    subroutines: HashMap<usize, Subroutine>,
+
+    // Subroutine and instruction count of every pending call
+    calls: Vec<(usize, u64)>,
+
+    instructions: u64,
+    frames: u64,
+    draws: u64,
+    frame_draws: u64,
+    max_frame_draws: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            counts: vec![0; MEM_SIZE],
+            opcodes: vec![0; MEM_SIZE],
+            reads: vec![0; MEM_SIZE],
+            mnemonics: HashMap::new(),
+            subroutines: HashMap::new(),
+            calls: Vec::new(),
+            instructions: 0,
+            frames: 0,
+            draws: 0,
+            frame_draws: 0,
+            max_frame_draws: 0,
+        }
+    }
+
+    // Counts the instruction at pc, call it right before Chip8::step
+    // unless the machine is waiting for a key
+    pub fn record(&mut self, chip8: &Chip8) {
+        let pc = chip8.pc();
+        let opcode = (chip8.peek(pc) as usize) << 8 | chip8.peek(pc + 1) as usize;
+
+        self.instructions += 1;
+        self.counts[pc % MEM_SIZE] += 1;
+        self.opcodes[pc % MEM_SIZE] = opcode;
+
+        let instruction = match Instruction::decode(opcode) {
+            Some(instruction) => instruction,
+            None => return,
+        };
+        *self.mnemonics.entry(instruction.mnemonic()).or_insert(0) += 1;
+
+        match instruction {
+            Instruction::Call(addr) => self.calls.push((addr, self.instructions - 1)),
+            Instruction::Ret => {
+                // A return without a call, e.g. the ROM fiddled with sp
+                if let Some((addr, start)) = self.calls.pop() {
+                    let subroutine = self.subroutines.entry(addr).or_default();
+                    subroutine.calls += 1;
+                    subroutine.instructions += self.instructions - start;
+                }
+            }
+            Instruction::Drw(_, _, n) => {
+                self.draws += 1;
+                self.frame_draws += 1;
+                self.read(chip8.i(), n);
+            }
+            Instruction::Load(x) => self.read(chip8.i(), x + 1),
+            _ => {}
+        }
+    }
+
+    fn read(&mut self, addr: usize, len: usize) {
+        for a in addr..addr + len {
+            self.reads[a % MEM_SIZE] += 1;
+        }
+    }
+
+    // Call after every 60 Hz frame
+    pub fn end_frame(&mut self) {
+        self.frames += 1;
+        self.max_frame_draws = self.max_frame_draws.max(self.frame_draws);
+        self.frame_draws = 0;
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn count(&self, addr: usize) -> u64 {
+        self.counts[addr % MEM_SIZE]
+    }
+
+    pub fn reads(&self, addr: usize) -> u64 {
+        self.reads[addr % MEM_SIZE]
+    }
+
+    // Last opcode executed at the address
+    pub fn opcode(&self, addr: usize) -> usize {
+        self.opcodes[addr % MEM_SIZE]
+    }
+
+    pub fn mnemonic_count(&self, mnemonic: &str) -> u64 {
+        self.mnemonics.get(mnemonic).copied().unwrap_or(0)
+    }
+
+    // Only subroutines that returned at least once
+    pub fn subroutine(&self, addr: usize) -> Option<Subroutine> {
+        self.subroutines.get(&addr).copied()
+    }
+
+    pub fn draws(&self) -> u64 {
+        self.draws
+    }
+
+    pub fn max_frame_draws(&self) -> u64 {
+        self.max_frame_draws
+    }
+
+    // Writes the hot spots, instruction types and subroutines using the
+    // most instructions
+    pub fn report<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let total = self.instructions.max(1) as f64;
+        let percent = |n: u64| n as f64 * 100.0 / total;
+
+        writeln!(
+            out,
+            "Profile: {} instructions in {} frames",
+            self.instructions, self.frames
+        )?;
+        writeln!(
+            out,
+            "DXYN draws: {} total, {:.1} per frame, {} at most",
+            self.draws,
+            self.draws as f64 / self.frames.max(1) as f64,
+            self.max_frame_draws
+        )?;
+
+        writeln!(out, "\nHot spots:")?;
+        let mut addresses: Vec<usize> = (0..MEM_SIZE).filter(|a| self.counts[*a] > 0).collect();
+        addresses.sort_by_key(|a| (std::cmp::Reverse(self.counts[*a]), *a));
+        for addr in addresses.into_iter().take(REPORT_TOP) {
+            let opcode = self.opcodes[addr];
+            let text = Instruction::decode(opcode)
+                .map(|i| i.to_string())
+                .unwrap_or_else(|| "???".to_string());
+            writeln!(
+                out,
+                "  0x{:04X}  {:04X}  {:<20} {:>10} {:>6.2}%",
+                addr,
+                opcode,
+                text,
+                self.counts[addr],
+                percent(self.counts[addr])
+            )?;
+        }
+
+        writeln!(out, "\nInstructions:")?;
+        let mut mnemonics: Vec<_> = self.mnemonics.iter().collect();
+        mnemonics.sort_by_key(|(m, n)| (std::cmp::Reverse(**n), **m));
+        for (mnemonic, n) in mnemonics {
+            writeln!(out, "  {:<6} {:>10} {:>6.2}%", mnemonic, n, percent(*n))?;
+        }
+
+        writeln!(out, "\nSubroutines:")?;
+        let mut subroutines: Vec<_> = self.subroutines.iter().collect();
+        subroutines.sort_by_key(|(a, s)| (std::cmp::Reverse(s.instructions), **a));
+        for (addr, s) in subroutines.into_iter().take(REPORT_TOP) {
+            writeln!(
+                out,
+                "  0x{:04X}  {:>8} calls {:>10} instructions {:>6.2}%",
+                addr,
+                s.calls,
+                s.instructions,
+                percent(s.instructions)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs a frame of n instructions, recording each one
+    fn run(profiler: &mut Profiler, chip8: &mut Chip8, n: usize) {
+        for _ in 0..n {
+            profiler.record(chip8);
+            chip8.step();
+        }
+        profiler.end_frame();
+    }
+
+    // Calls the subroutine at 0x206 twice, it draws and returns
+    const PROGRAM: [u8; 10] = [0x22, 0x06, 0x22, 0x06, 0x12, 0x04, 0xD0, 0x01, 0x00, 0xEE];
+
+    #[test]
+    fn test_counts() {
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(&PROGRAM).unwrap();
+        let mut profiler = Profiler::new();
+
+        run(&mut profiler, &mut chip8, 6);
+        run(&mut profiler, &mut chip8, 3);
+
+        assert_eq!(profiler.instructions(), 9);
+        assert_eq!(profiler.count(0x206), 2);
+        assert_eq!(profiler.count(0x204), 3);
+        assert_eq!(profiler.mnemonic_count("CALL"), 2);
+        assert_eq!(profiler.mnemonic_count("JP"), 3);
+        assert_eq!(profiler.draws(), 2);
+        assert_eq!(profiler.max_frame_draws(), 2);
+        assert_eq!(
+            profiler.subroutine(0x206),
+            Some(Subroutine {
+                calls: 2,
+                instructions: 6,
+            })
+        );
+        assert_eq!(profiler.subroutine(0x204), None);
+        assert_eq!(profiler.reads(0x000), 2); // Font of 0, drawn 1 row high
+        assert_eq!(profiler.reads(0x001), 0);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(&PROGRAM).unwrap();
+        let mut profiler = Profiler::new();
+        run(&mut profiler, &mut chip8, 9);
+
+        let mut out = Vec::new();
+        profiler.report(&mut out).unwrap();
+        let report = String::from_utf8(out).unwrap();
+
+        assert!(report.starts_with("Profile: 9 instructions in 1 frames\n"));
+        assert!(report.contains("DXYN draws: 2 total, 2.0 per frame, 2 at most"));
+        assert!(report.contains("  0x0204  1204  JP 0x204"));
+        assert!(report.contains("  CALL            2"));
+        assert!(report.contains("  0x0206         2 calls          6 instructions"));
+    }
+}
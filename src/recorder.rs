@@ -0,0 +1,314 @@
+use crate::chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::palette::Palette;
+use crate::screenshot;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// Frames are captured at the CHIP-8 timer rate
+pub const FRAME_RATE: u32 = 60;
+
+const SAMPLE_RATE: u32 = 44_100;
+const SAMPLES_PER_FRAME: u32 = SAMPLE_RATE / FRAME_RATE;
+const BEEP_FREQUENCY: u32 = 440;
+const BEEP_AMPLITUDE: i16 = 8_000;
+
+// Output of the video stream, picked from the file extension
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    // Animated GIF
+    Gif,
+
+    // YUV4MPEG2 stream, 4:4:4
+    Y4m,
+
+    // Concatenated binary PPM (P6) frames, e.g. for ffmpeg -f image2pipe
+    Ppm,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "gif" => Some(Format::Gif),
+            "y4m" => Some(Format::Y4m),
+            "ppm" => Some(Format::Ppm),
+            _ => None,
+        }
+    }
+}
+
+enum Video {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Stream(BufWriter<File>),
+}
+
+pub struct Recorder {
+    format: Format,
+    video: Video,
+    scale: usize,
+    palette: Palette,
+
+    // Beep captured to a WAV file alongside the video
+    audio: Option<WavWriter>,
+
+    // Number of frames captured so far
+    frames: u64,
+}
+
+impl Recorder {
+    // Starts recording to path, the format is picked from its extension.
+    // If with_audio is set, the beep is recorded to the same path with a .wav extension.
+    pub fn start<P: AsRef<Path>>(
+        path: P,
+        scale: usize,
+        palette: &Palette,
+        with_audio: bool,
+    ) -> Result<Recorder, Box<dyn Error>> {
+        let path = path.as_ref();
+        let format = Format::from_path(path)
+            .ok_or(format!("Unknown recording format: {}", path.display()))?;
+
+        if scale == 0 {
+            return Err("Recording scale must be at least 1".into());
+        }
+
+        let width = SCREEN_WIDTH * scale;
+        let height = SCREEN_HEIGHT * scale;
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        let video = match format {
+            Format::Gif => {
+                let colors = [palette.off, palette.on].concat();
+                let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &colors)?;
+                encoder.set_repeat(gif::Repeat::Infinite)?;
+                Video::Gif(encoder)
+            }
+            Format::Y4m => {
+                writeln!(
+                    writer,
+                    "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                    width, height, FRAME_RATE
+                )?;
+                Video::Stream(writer)
+            }
+            Format::Ppm => Video::Stream(writer),
+        };
+
+        let audio = if with_audio {
+            Some(WavWriter::create(audio_path(path))?)
+        } else {
+            None
+        };
+
+        Ok(Recorder {
+            format,
+            video,
+            scale,
+            palette: *palette,
+            audio,
+            frames: 0,
+        })
+    }
+
+    // Captures one 1/60 s frame of the framebuffer and the beep state
+    pub fn capture(&mut self, screen: &[u8], beeping: bool) -> Result<(), Box<dyn Error>> {
+        let width = SCREEN_WIDTH * self.scale;
+        let height = SCREEN_HEIGHT * self.scale;
+
+        match &mut self.video {
+            Video::Gif(encoder) => {
+                let mut buffer = Vec::with_capacity(width * height);
+                for row in 0..height {
+                    for col in 0..width {
+                        let pixel = screen[col / self.scale + (row / self.scale) * SCREEN_WIDTH];
+                        buffer.push((pixel != 0) as u8);
+                    }
+                }
+
+                let frame = gif::Frame {
+                    width: width as u16,
+                    height: height as u16,
+                    buffer: buffer.into(),
+                    delay: gif_delay(self.frames),
+                    ..Default::default()
+                };
+                encoder.write_frame(&frame)?;
+            }
+            Video::Stream(writer) => {
+                let rgb = screenshot::to_rgb(screen, self.scale, &self.palette);
+
+                if self.format == Format::Y4m {
+                    writer.write_all(b"FRAME\n")?;
+                    writer.write_all(&rgb_to_yuv444(&rgb))?;
+                } else {
+                    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+                    writer.write_all(&rgb)?;
+                }
+            }
+        }
+
+        if let Some(audio) = &mut self.audio {
+            audio.write_frame(beeping)?;
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+
+    // Number of frames captured so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // Flushes and closes all outputs
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self.video {
+            Video::Gif(encoder) => encoder.into_inner()?.flush()?,
+            Video::Stream(mut writer) => writer.flush()?,
+        }
+
+        if let Some(audio) = self.audio {
+            audio.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+// Path of the WAV file recorded alongside the video
+pub fn audio_path(path: &Path) -> PathBuf {
+    path.with_extension("wav")
+}
+
+// GIF delays are in 1/100 s, so alternate them to average 60 frames per second
+fn gif_delay(frame: u64) -> u16 {
+    let start = frame * 100 / FRAME_RATE as u64;
+    let end = (frame + 1) * 100 / FRAME_RATE as u64;
+
+    (end - start) as u16
+}
+
+// Converts RGB24 pixels into planar Y, U, V (BT.601, studio swing)
+fn rgb_to_yuv444(rgb: &[u8]) -> Vec<u8> {
+    let pixels = rgb.len() / 3;
+    let mut yuv = vec![0; pixels * 3];
+
+    for (i, p) in rgb.chunks(3).enumerate() {
+        let (r, g, b) = (p[0] as f32, p[1] as f32, p[2] as f32);
+
+        yuv[i] = (16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8;
+        yuv[pixels + i] = (128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8;
+        yuv[pixels * 2 + i] = (128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8;
+    }
+
+    yuv
+}
+
+// 16 bit mono PCM WAV file, the header is completed on finish()
+struct WavWriter {
+    writer: BufWriter<File>,
+
+    // Number of samples written so far, also the phase of the beep
+    samples: u32,
+}
+
+impl WavWriter {
+    fn create(path: PathBuf) -> Result<WavWriter, Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        // Sizes are filled in by finish()
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0_u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16_u32.to_le_bytes())?; // fmt chunk size
+        writer.write_all(&1_u16.to_le_bytes())?; // PCM
+        writer.write_all(&1_u16.to_le_bytes())?; // Mono
+        writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // Byte rate
+        writer.write_all(&2_u16.to_le_bytes())?; // Block align
+        writer.write_all(&16_u16.to_le_bytes())?; // Bits per sample
+        writer.write_all(b"data")?;
+        writer.write_all(&0_u32.to_le_bytes())?;
+
+        Ok(WavWriter { writer, samples: 0 })
+    }
+
+    // Writes 1/60 s of square wave beep, or silence
+    fn write_frame(&mut self, beeping: bool) -> Result<(), Box<dyn Error>> {
+        let half_period = SAMPLE_RATE / BEEP_FREQUENCY / 2;
+
+        for _ in 0..SAMPLES_PER_FRAME {
+            let sample = if !beeping {
+                0
+            } else if (self.samples / half_period).is_multiple_of(2) {
+                BEEP_AMPLITUDE
+            } else {
+                -BEEP_AMPLITUDE
+            };
+
+            self.writer.write_all(&sample.to_le_bytes())?;
+            self.samples += 1;
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        let data_size = self.samples * 2;
+
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path(Path::new("a.gif")), Some(Format::Gif));
+        assert_eq!(Format::from_path(Path::new("a.y4m")), Some(Format::Y4m));
+        assert_eq!(Format::from_path(Path::new("a.ppm")), Some(Format::Ppm));
+        assert_eq!(Format::from_path(Path::new("a.mp4")), None);
+    }
+
+    #[test]
+    fn test_gif_delay() {
+        let total: u64 = (0..FRAME_RATE as u64).map(|f| gif_delay(f) as u64).sum();
+
+        assert_eq!(total, 100);
+    }
+
+    #[test]
+    fn test_record_y4m_with_audio() {
+        let path = env::temp_dir().join("chip8_rust_test_record.y4m");
+        let screen = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+        let mut recorder = Recorder::start(&path, 1, &Palette::DEFAULT, true).unwrap();
+        recorder.capture(&screen, false).unwrap();
+        recorder.capture(&screen, true).unwrap();
+        assert_eq!(recorder.frames(), 2);
+        recorder.finish().unwrap();
+
+        let header = format!("YUV4MPEG2 W64 H32 F{}:1 Ip A1:1 C444\n", FRAME_RATE);
+        let frame_size = b"FRAME\n".len() + SCREEN_WIDTH * SCREEN_HEIGHT * 3;
+        let video = fs::read(&path).unwrap();
+        assert_eq!(video.len(), header.len() + frame_size * 2);
+
+        let audio = fs::read(audio_path(&path)).unwrap();
+        assert_eq!(audio.len(), 44 + (SAMPLES_PER_FRAME * 2 * 2) as usize);
+        assert_eq!(audio[0..4], *b"RIFF");
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(audio_path(&path)).unwrap();
+    }
+}
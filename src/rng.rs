@@ -0,0 +1,74 @@
+// xorshift64* generator, small enough for the CXNN instruction and
+// usable without std
+#[derive(Clone, Debug)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    // Seeded from the OS, or with a fixed seed without std
+    pub fn new() -> XorShift {
+        #[cfg(feature = "std")]
+        let seed = rand::random::<u64>();
+        #[cfg(not(feature = "std"))]
+        let seed = 0x2545_F491_4F6C_DD1D;
+
+        XorShift::from_seed(seed)
+    }
+
+    pub fn from_seed(seed: u64) -> XorShift {
+        // State must never be 0
+        XorShift {
+            state: if seed == 0 {
+                0x2545_F491_4F6C_DD1D
+            } else {
+                seed
+            },
+        }
+    }
+
+    // Current state, restoring it with from_seed() continues the sequence
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        // Upper bits have the best quality
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+impl Default for XorShift {
+    fn default() -> XorShift {
+        XorShift::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed() {
+        let mut a = XorShift::from_seed(7);
+        let mut b = XorShift::from_seed(7);
+
+        for _ in 0..16 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_zero_seed() {
+        let mut rng = XorShift::from_seed(0);
+
+        assert_ne!(rng.next_u64(), 0);
+    }
+}
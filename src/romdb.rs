@@ -0,0 +1,268 @@
+// Database of known ROMs keyed by the SHA-1 of the ROM, giving their
+// title and the settings they need. The bundled database is roms.txt,
+// users can add their own entries in a file of the same format:
+//
+//     # Comment
+//     [0123456789abcdef0123456789abcdef01234567]
+//     title = Some Game
+//     author = Someone
+//     platform = schip
+//     ips = 700
+//     quirks = vip
+//     keys = Left:7, Right:9, Space:6
+//     palette = amber
+//     filter = blend
+//
+// Every setting is optional. keys maps keyboard keys, by SDL name, to
+// CHIP-8 keys. quirks takes a preset like --quirks, without it the
+// quirks of the platform are used. filter is a display filter of the SDL
+// frontend like --filter, checked by the frontend.
+use crate::palette::Palette;
+use crate::platform::{Platform, Quirks};
+use crate::sha1::sha1;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+const BUNDLED: &str = include_str!("roms.txt");
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RomInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub platform: Option<Platform>,
+    pub ips: Option<u32>,
+    pub quirks: Option<Quirks>,
+
+    // Keyboard key name and CHIP-8 key
+    pub keys: Vec<(String, usize)>,
+
+    pub palette: Option<Palette>,
+    pub filter: Option<String>,
+}
+
+impl RomInfo {
+    // The quirks, or else those of the platform
+    pub fn platform_quirks(&self) -> Option<Quirks> {
+        self.quirks.or_else(|| self.platform.map(Platform::quirks))
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "title" => self.title = Some(value.to_string()),
+            "author" => self.author = Some(value.to_string()),
+            "platform" => self.platform = Some(value.parse()?),
+            "ips" => {
+                let ips = value
+                    .parse()
+                    .ok()
+                    .filter(|ips| *ips > 0)
+                    .ok_or(format!("Invalid IPS: {}", value))?;
+                self.ips = Some(ips);
+            }
+            "quirks" => self.quirks = Some(value.parse()?),
+            "keys" => self.keys = parse_keys(value)?,
+            "palette" => self.palette = Some(value.parse()?),
+            "filter" => self.filter = Some(value.to_string()),
+            _ => return Err(format!("Unknown setting: {}", name)),
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RomDb {
+    roms: HashMap<[u8; 20], RomInfo>,
+}
+
+impl RomDb {
+    pub fn new() -> RomDb {
+        RomDb::default()
+    }
+
+    // The database shipped with the emulator
+    pub fn bundled() -> &'static RomDb {
+        static BUNDLED_DB: OnceLock<RomDb> = OnceLock::new();
+
+        BUNDLED_DB.get_or_init(|| RomDb::parse(BUNDLED).expect("Invalid bundled ROM database"))
+    }
+
+    // Errors name the line, e.g. "3: Unknown setting: name"
+    pub fn parse(text: &str) -> Result<RomDb, String> {
+        let mut db = RomDb::new();
+        let mut current = None;
+
+        for (line_number, line) in text.lines().enumerate() {
+            let error = |e: String| format!("{}: {}", line_number + 1, e);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(hash) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let hash =
+                    parse_sha1(hash).ok_or_else(|| error(format!("Invalid SHA-1: {}", hash)))?;
+                db.roms.insert(hash, RomInfo::default());
+                current = Some(hash);
+                continue;
+            }
+
+            let hash =
+                current.ok_or_else(|| error("Setting outside of a [sha1] section".into()))?;
+            let (name, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), line[i + 1..].trim()),
+                None => return Err(error(format!("Missing value for {}", line))),
+            };
+
+            let info = db.roms.get_mut(&hash).unwrap();
+            info.set(name, value).map_err(error)?;
+        }
+
+        Ok(db)
+    }
+
+    // Reads a user database, errors name the file and line
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<RomDb, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+
+        RomDb::parse(&text).map_err(|e| format!("{}:{}", path.display(), e))
+    }
+
+    // Adds the entries of other, replacing those of the same ROMs
+    pub fn merge(&mut self, other: RomDb) {
+        self.roms.extend(other.roms);
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn get(&self, sha1: &[u8; 20]) -> Option<&RomInfo> {
+        self.roms.get(sha1)
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.get(&sha1(rom))
+    }
+}
+
+// Lowercase hex, like sha1sum prints
+pub fn to_hex(sha1: &[u8; 20]) -> String {
+    sha1.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_sha1(s: &str) -> Option<[u8; 20]> {
+    if s.len() != 40 || !s.is_ascii() {
+        return None;
+    }
+
+    let mut hash = [0; 20];
+    for (i, b) in hash.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Some(hash)
+}
+
+// Parses "Left:7, Right:9"
+fn parse_keys(s: &str) -> Result<Vec<(String, usize)>, String> {
+    s.split(',')
+        .map(|mapping| {
+            let error = || format!("Invalid key mapping: {}", mapping.trim());
+            let i = mapping.rfind(':').ok_or_else(error)?;
+            let name = mapping[..i].trim();
+            let key = usize::from_str_radix(mapping[i + 1..].trim(), 16)
+                .ok()
+                .filter(|k| *k < 16)
+                .ok_or_else(error)?;
+
+            if name.is_empty() {
+                return Err(error());
+            }
+            Ok((name.to_string(), key))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 2] = [0x12, 0x00];
+
+    fn db() -> String {
+        format!(
+            "# Test\n\
+             [{}]\n\
+             title = Spin\n\
+             platform = schip\n\
+             ips = 700\n\
+             keys = Left:7, Left Shift:A\n\
+             palette = amber\n\
+             filter = decay:0.5\n",
+            to_hex(&sha1(&ROM))
+        )
+    }
+
+    #[test]
+    fn test_lookup() {
+        let db = RomDb::parse(&db()).unwrap();
+        let info = db.lookup(&ROM).unwrap();
+
+        assert_eq!(info.title.as_deref(), Some("Spin"));
+        assert_eq!(info.author, None);
+        assert_eq!(info.ips, Some(700));
+        assert_eq!(info.platform_quirks(), Some(Quirks::SCHIP));
+        assert_eq!(
+            info.keys,
+            [("Left".to_string(), 7), ("Left Shift".to_string(), 0xA)]
+        );
+        assert_eq!(info.palette, Some(Palette::AMBER));
+        assert_eq!(info.filter.as_deref(), Some("decay:0.5"));
+        assert!(db.lookup(&[0x00, 0xE0]).is_none());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut db = RomDb::parse(&db()).unwrap();
+        let hash = to_hex(&sha1(&ROM));
+        let user = RomDb::parse(&format!("[{}]\nquirks = vip\n", hash.to_uppercase())).unwrap();
+
+        db.merge(user);
+        let info = db.lookup(&ROM).unwrap();
+        assert_eq!(db.len(), 1);
+        assert_eq!(info.title, None);
+        assert_eq!(info.platform_quirks(), Some(Quirks::VIP));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |text: &str| RomDb::parse(text).unwrap_err();
+        let section = format!("[{}]\n", "0".repeat(40));
+
+        assert_eq!(error("title = x"), "1: Setting outside of a [sha1] section");
+        assert_eq!(error("[abc]"), "1: Invalid SHA-1: abc");
+        assert_eq!(
+            error(&format!("{}name = x", section)),
+            "2: Unknown setting: name"
+        );
+        assert!(RomDb::parse(&format!("{}ips = 0", section)).is_err());
+        assert!(RomDb::parse(&format!("{}keys = Left:10", section)).is_err());
+        assert!(RomDb::parse(&format!("{}keys = :1", section)).is_err());
+        assert!(RomDb::parse(&format!("{}platform", section)).is_err());
+    }
+
+    #[test]
+    fn test_bundled() {
+        // Parses without panicking
+        RomDb::bundled();
+    }
+}
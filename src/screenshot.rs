@@ -0,0 +1,100 @@
+use crate::chip8::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::palette::Palette;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Converts the framebuffer into RGB24 pixels, each pixel scaled to scale x scale
+pub fn to_rgb(screen: &[u8], scale: usize, palette: &Palette) -> Vec<u8> {
+    let width = SCREEN_WIDTH * scale;
+    let mut rgb = Vec::with_capacity(width * SCREEN_HEIGHT * scale * 3);
+
+    for row in 0..SCREEN_HEIGHT * scale {
+        for col in 0..width {
+            let pixel = screen[col / scale + (row / scale) * SCREEN_WIDTH];
+            rgb.extend_from_slice(&palette.color(pixel));
+        }
+    }
+
+    rgb
+}
+
+// Encodes the framebuffer as PNG
+pub fn write_png<W: Write>(
+    writer: W,
+    screen: &[u8],
+    scale: usize,
+    palette: &Palette,
+) -> Result<(), Box<dyn Error>> {
+    if scale == 0 {
+        return Err("Screenshot scale must be at least 1".into());
+    }
+
+    let mut encoder = png::Encoder::new(
+        writer,
+        (SCREEN_WIDTH * scale) as u32,
+        (SCREEN_HEIGHT * scale) as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut png_writer = encoder.write_header()?;
+    png_writer.write_image_data(&to_rgb(screen, scale, palette))?;
+
+    Ok(())
+}
+
+// Saves the framebuffer to a PNG file
+pub fn save_png<P: AsRef<Path>>(
+    path: P,
+    screen: &[u8],
+    scale: usize,
+    palette: &Palette,
+) -> Result<(), Box<dyn Error>> {
+    let f = File::create(path)?;
+
+    write_png(BufWriter::new(f), screen, scale, palette)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_rgb_scale() {
+        let mut screen = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        screen[1] = 1;
+
+        let rgb = to_rgb(&screen, 2, &Palette::AMBER);
+
+        assert_eq!(rgb.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4 * 3);
+        // Pixel (1, 0) covers (2, 0) to (3, 1) when scaled
+        assert_eq!(rgb[0..3], Palette::AMBER.off);
+        assert_eq!(rgb[2 * 3..2 * 3 + 3], Palette::AMBER.on);
+        assert_eq!(rgb[3 * 3..3 * 3 + 3], Palette::AMBER.on);
+        let row = SCREEN_WIDTH * 2 * 3;
+        assert_eq!(rgb[row + 3 * 3..row + 3 * 3 + 3], Palette::AMBER.on);
+        assert_eq!(rgb[row + 4 * 3..row + 4 * 3 + 3], Palette::AMBER.off);
+    }
+
+    #[test]
+    fn test_write_png() {
+        let screen = [1; SCREEN_WIDTH * SCREEN_HEIGHT];
+        let mut buffer = Vec::new();
+
+        write_png(&mut buffer, &screen, 1, &Palette::DEFAULT).unwrap();
+
+        let decoder = png::Decoder::new(&buffer[..]);
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().width, SCREEN_WIDTH as u32);
+        assert_eq!(reader.info().height, SCREEN_HEIGHT as u32);
+    }
+
+    #[test]
+    fn test_write_png_zero_scale() {
+        let screen = [0; SCREEN_WIDTH * SCREEN_HEIGHT];
+
+        assert!(write_png(Vec::new(), &screen, 0, &Palette::DEFAULT).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::cli::MAX_IPS;
+use chip8_core::recorder::FRAME_RATE;
+use std::time::Duration;
+
+// Slowest and fastest speed, as powers of two of real time
+const MIN_SCALE: i32 = -3;
+const MAX_SCALE: i32 = 3;
+
+// Emulation speed changed at runtime by hotkeys
+#[derive(Debug, PartialEq)]
+pub struct Speed {
+    ips: u32,
+
+    // Frames run 2^scale times as fast as real time
+    scale: i32,
+
+    // Frames run as fast as possible while set
+    fast_forward: bool,
+}
+
+impl Speed {
+    pub fn new(ips: u32) -> Speed {
+        Speed {
+            ips,
+            scale: 0,
+            fast_forward: false,
+        }
+    }
+
+    pub fn ips(&self) -> u32 {
+        self.ips
+    }
+
+    // One more instruction per frame, up to the largest --ips
+    pub fn increase_ips(&mut self) {
+        self.ips = (self.ips + FRAME_RATE).min(MAX_IPS);
+    }
+
+    // One less instruction per frame, at least one instruction per second
+    pub fn decrease_ips(&mut self) {
+        self.ips = self.ips.saturating_sub(FRAME_RATE).max(1);
+    }
+
+    // Doubles the speed, up to 8x
+    pub fn faster(&mut self) {
+        self.scale = (self.scale + 1).min(MAX_SCALE);
+    }
+
+    // Halves the speed, down to 1/8x
+    pub fn slower(&mut self) {
+        self.scale = (self.scale - 1).max(MIN_SCALE);
+    }
+
+    pub fn set_fast_forward(&mut self, enabled: bool) {
+        self.fast_forward = enabled;
+    }
+
+    // Real time a frame of the given duration takes, None when uncapped
+    pub fn frame_duration(&self, frame: Duration) -> Option<Duration> {
+        if self.fast_forward {
+            None
+        } else if self.scale >= 0 {
+            Some(frame / (1 << self.scale))
+        } else {
+            Some(frame * (1 << -self.scale))
+        }
+    }
+
+    // Shown in the window title, e.g. "700 IPS, 1/2x"
+    pub fn label(&self) -> String {
+        let speed = if self.fast_forward {
+            ", fast-forward".to_string()
+        } else if self.scale > 0 {
+            format!(", {}x", 1 << self.scale)
+        } else if self.scale < 0 {
+            format!(", 1/{}x", 1 << -self.scale)
+        } else {
+            String::new()
+        };
+
+        format!("{} IPS{}", self.ips, speed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    #[test]
+    fn test_scale() {
+        let mut speed = Speed::new(700);
+        assert_eq!(speed.frame_duration(FRAME), Some(FRAME));
+        assert_eq!(speed.label(), "700 IPS");
+
+        speed.faster();
+        assert_eq!(speed.frame_duration(FRAME), Some(Duration::from_millis(8)));
+        assert_eq!(speed.label(), "700 IPS, 2x");
+
+        for _ in 0..6 {
+            speed.slower();
+        }
+        assert_eq!(speed.frame_duration(FRAME), Some(FRAME * 8));
+        assert_eq!(speed.label(), "700 IPS, 1/8x");
+
+        speed.set_fast_forward(true);
+        assert_eq!(speed.frame_duration(FRAME), None);
+        assert_eq!(speed.label(), "700 IPS, fast-forward");
+    }
+
+    #[test]
+    fn test_ips() {
+        let mut speed = Speed::new(100);
+
+        speed.increase_ips();
+        assert_eq!(speed.ips(), 160);
+        speed.decrease_ips();
+        speed.decrease_ips();
+        assert_eq!(speed.ips(), 40);
+        speed.decrease_ips();
+        assert_eq!(speed.ips(), 1);
+
+        let mut speed = Speed::new(MAX_IPS - 1);
+        speed.increase_ips();
+        assert_eq!(speed.ips(), MAX_IPS);
+        speed.increase_ips();
+        assert_eq!(speed.ips(), MAX_IPS);
+    }
+}
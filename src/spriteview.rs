@@ -0,0 +1,280 @@
+// Sprite viewer in a second window, opened with F3: the bytes at I, or at
+// any address, as the 8 x N bitmap DXYN draws from them, or as a 16 x 16
+// bitmap with two bytes per row. Also shows the last DXYN executed.
+use crate::display;
+use crate::overlay::GLYPH_HEIGHT;
+use chip8_core::chip8::MEM_SIZE;
+use chip8_core::palette::Palette;
+use chip8_core::Chip8;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+use sdl2::VideoSubsystem;
+
+// Side of a sprite pixel
+const PIXEL_SIZE: i32 = 16;
+
+// Side of a font pixel
+const FONT_SIZE: i32 = 2;
+
+// Side of the bitmap area, fitting a 16 x 16 sprite
+const BITMAP_SIZE: i32 = 16 * PIXEL_SIZE;
+
+// Lines of text under the bitmap
+const TEXT_LINES: i32 = 2;
+
+// Height DXYN sprites are shown with at first, the font's
+const DEFAULT_HEIGHT: usize = 5;
+
+pub struct SpriteView {
+    // Shown address when not following I
+    addr: usize,
+    follow_i: bool,
+
+    // Rows of 8 x N sprites, 1 to 15
+    height: usize,
+
+    // 16 x 16 instead of 8 x height
+    large: bool,
+}
+
+impl SpriteView {
+    pub fn new() -> SpriteView {
+        SpriteView {
+            addr: 0,
+            follow_i: true,
+            height: DEFAULT_HEIGHT,
+            large: false,
+        }
+    }
+
+    pub fn addr(&self, chip8: &Chip8) -> usize {
+        if self.follow_i {
+            chip8.i() % MEM_SIZE
+        } else {
+            self.addr
+        }
+    }
+
+    // Width and height in pixels
+    pub fn size(&self) -> (usize, usize) {
+        if self.large {
+            (16, 16)
+        } else {
+            (8, self.height)
+        }
+    }
+
+    // Rows of the sprite, most significant bit on the left
+    pub fn rows(&self, chip8: &Chip8) -> Vec<u16> {
+        let addr = self.addr(chip8);
+        let (_, height) = self.size();
+
+        (0..height)
+            .map(|row| {
+                if self.large {
+                    let high = chip8.peek(addr + row * 2) as u16;
+                    high << 8 | chip8.peek(addr + row * 2 + 1) as u16
+                } else {
+                    (chip8.peek(addr + row) as u16) << 8
+                }
+            })
+            .collect()
+    }
+
+    // Moves away from I by delta bytes, wrapping around memory
+    pub fn move_by(&mut self, chip8: &Chip8, delta: isize) {
+        let addr = self.addr(chip8) as isize + delta;
+        self.addr = addr.rem_euclid(MEM_SIZE as isize) as usize;
+        self.follow_i = false;
+    }
+
+    pub fn follow_i(&mut self) {
+        self.follow_i = true;
+    }
+
+    // Adds delta rows to 8 x N sprites, 1 to 15
+    pub fn resize(&mut self, delta: isize) {
+        self.height = (self.height as isize + delta).clamp(1, 15) as usize;
+    }
+
+    pub fn toggle_large(&mut self) {
+        self.large = !self.large;
+    }
+
+    // Bytes the sprite takes
+    pub fn len(&self) -> usize {
+        let (width, height) = self.size();
+        width / 8 * height
+    }
+
+    pub fn label(&self, chip8: &Chip8) -> String {
+        let (width, height) = self.size();
+        let source = if self.follow_i { "I" } else { "address" };
+        format!(
+            "{}x{} sprite at 0x{:03X} ({})",
+            width,
+            height,
+            self.addr(chip8),
+            source
+        )
+    }
+}
+
+pub struct SpriteWindow {
+    canvas: WindowCanvas,
+    view: SpriteView,
+}
+
+impl SpriteWindow {
+    pub fn open(video: &VideoSubsystem) -> Result<SpriteWindow, String> {
+        let line_height = (GLYPH_HEIGHT as i32 + 3) * FONT_SIZE;
+        let width = BITMAP_SIZE + 4 * FONT_SIZE;
+        let height = BITMAP_SIZE + TEXT_LINES * line_height + 6 * FONT_SIZE;
+
+        let window = video
+            .window("Sprite", width as u32, height as u32)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+
+        Ok(SpriteWindow {
+            canvas,
+            view: SpriteView::new(),
+        })
+    }
+
+    // Whether the event is for this window
+    pub fn owns(&self, event: &Event) -> bool {
+        event.get_window_id() == Some(self.canvas.window().id())
+    }
+
+    // Handles an event of this window. Returns false when it's closed.
+    pub fn handle(&mut self, event: &Event, chip8: &Chip8) -> bool {
+        let keycode = match event {
+            Event::Window {
+                win_event: WindowEvent::Close,
+                ..
+            } => return false,
+            Event::KeyDown {
+                keycode: Some(k), ..
+            } => *k,
+            _ => return true,
+        };
+
+        let len = self.view.len() as isize;
+        match keycode {
+            Keycode::F3 => return false,
+            Keycode::Left => self.view.move_by(chip8, -1),
+            Keycode::Right => self.view.move_by(chip8, 1),
+            Keycode::Up => self.view.move_by(chip8, -len),
+            Keycode::Down => self.view.move_by(chip8, len),
+            Keycode::I => self.view.follow_i(),
+            Keycode::Minus | Keycode::KpMinus => self.view.resize(-1),
+            Keycode::Equals | Keycode::KpPlus => self.view.resize(1),
+            Keycode::L => self.view.toggle_large(),
+            _ => {}
+        }
+
+        true
+    }
+
+    pub fn draw(&mut self, chip8: &Chip8, palette: &Palette) -> Result<(), String> {
+        let [r, g, b] = palette.off;
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+        self.canvas.set_blend_mode(BlendMode::Blend);
+
+        // Outline of the sprite, then its pixels
+        let margin = 2 * FONT_SIZE;
+        let (width, height) = self.view.size();
+        let [r, g, b] = palette.on;
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.draw_rect(Rect::new(
+            margin - 1,
+            margin - 1,
+            width as u32 * PIXEL_SIZE as u32 + 2,
+            height as u32 * PIXEL_SIZE as u32 + 2,
+        ))?;
+
+        let mut pixels = Vec::new();
+        for (y, row) in self.view.rows(chip8).iter().enumerate() {
+            for x in 0..width {
+                if row & (0x8000 >> x) != 0 {
+                    pixels.push(Rect::new(
+                        margin + x as i32 * PIXEL_SIZE,
+                        margin + y as i32 * PIXEL_SIZE,
+                        PIXEL_SIZE as u32,
+                        PIXEL_SIZE as u32,
+                    ));
+                }
+            }
+        }
+        self.canvas.fill_rects(&pixels)?;
+
+        let last_draw = match chip8.last_draw() {
+            Some(draw) => format!(
+                "Last: 8x{} from {:03X} at {},{}{}",
+                draw.height,
+                draw.addr,
+                draw.x,
+                draw.y,
+                if draw.collision { ", hit" } else { "" }
+            ),
+            None => "Last: none".to_string(),
+        };
+        let line_height = (GLYPH_HEIGHT as i32 + 3) * FONT_SIZE;
+        let colors = [palette.on, palette.off];
+        for (line, text) in [self.view.label(chip8), last_draw].iter().enumerate() {
+            let y = BITMAP_SIZE + 2 * margin + line as i32 * line_height;
+            display::draw_text(&mut self.canvas, text, margin, y, FONT_SIZE, colors)?;
+        }
+
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::chip8::PC_START;
+
+    #[test]
+    fn test_rows() {
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(&[0xF0, 0x90, 0xF0, 0x90, 0x90]).unwrap();
+        chip8.set_i(PC_START);
+        let mut view = SpriteView::new();
+
+        assert_eq!(view.rows(&chip8), [0xF000, 0x9000, 0xF000, 0x9000, 0x9000]);
+        assert_eq!(view.label(&chip8), "8x5 sprite at 0x200 (I)");
+
+        view.toggle_large();
+        assert_eq!(view.size(), (16, 16));
+        assert_eq!(view.rows(&chip8)[..3], [0xF090, 0xF090, 0x9000]);
+    }
+
+    #[test]
+    fn test_move() {
+        let mut chip8 = Chip8::init();
+        chip8.set_i(0x300);
+        let mut view = SpriteView::new();
+
+        view.move_by(&chip8, -0x301);
+        assert_eq!(view.addr(&chip8), MEM_SIZE - 1);
+        chip8.set_i(0x400);
+        assert_eq!(view.addr(&chip8), MEM_SIZE - 1);
+        assert_eq!(view.label(&chip8), "8x5 sprite at 0xFFF (address)");
+
+        view.follow_i();
+        assert_eq!(view.addr(&chip8), 0x400);
+
+        view.resize(20);
+        assert_eq!(view.size(), (8, 15));
+        view.resize(-20);
+        assert_eq!(view.len(), 1);
+    }
+}
@@ -0,0 +1,175 @@
+// COSMAC VIP timing: every instruction costs the machine cycles the
+// original interpreter took, and a 60 Hz frame has a fixed budget of them.
+//
+// A machine cycle is 8 clocks of the 1.76 MHz CDP1802, about 4.54 us.
+// Costs are approximations rounded from published measurements of the
+// interpreter, they don't model every data-dependent path.
+use crate::chip8::Chip8;
+use crate::disasm::Instruction;
+
+// Machine cycles in a 60 Hz frame
+pub const CYCLES_PER_FRAME: u32 = 3668;
+
+// Cycles taken by the display DMA and the interrupt routine every frame
+pub const FRAME_OVERHEAD: u32 = 1024 + 100;
+
+// Machine cycles the instruction takes. skipped is whether a skip
+// instruction skipped the next one.
+pub fn cycles(instruction: Instruction, skipped: bool) -> u32 {
+    let skip = if skipped { 2 } else { 0 };
+
+    match instruction {
+        Instruction::Cls => 24,
+        Instruction::Ret | Instruction::Jp(_) | Instruction::Call(_) | Instruction::JpV0(_) => 23,
+        Instruction::SeByte(..) | Instruction::SneByte(..) => 12 + skip,
+        Instruction::SeReg(..) | Instruction::SneReg(..) => 16 + skip,
+        Instruction::LdByte(..) => 6,
+        Instruction::AddByte(..) => 10,
+        Instruction::LdReg(..)
+        | Instruction::Or(..)
+        | Instruction::And(..)
+        | Instruction::Xor(..)
+        | Instruction::AddReg(..)
+        | Instruction::Sub(..)
+        | Instruction::Shr(..)
+        | Instruction::Subn(..)
+        | Instruction::Shl(..) => 44,
+        Instruction::LdI(_) => 12,
+        Instruction::Rnd(..) => 36,
+        // Rows are shifted into place and XORed one by one
+        Instruction::Drw(_, _, n) => 26 + 12 * n as u32,
+        Instruction::Skp(_) | Instruction::Sknp(_) => 16 + skip,
+        Instruction::LdVxDt(_)
+        | Instruction::LdVxKey(_)
+        | Instruction::LdDtVx(_)
+        | Instruction::LdStVx(_) => 10,
+        Instruction::AddI(_) => 19,
+        Instruction::LdFont(_) => 20,
+        Instruction::LdBcd(_) => 204,
+        Instruction::Store(x) | Instruction::Load(x) => 14 + 14 * (x as u32 + 1),
+    }
+}
+
+// Schedules instructions by cycles instead of a fixed count per frame.
+// Timers should be ticked per frame, see Chip8::set_timers_per_frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VipTiming {
+    // Cycles left in the current frame, negative when the last
+    // instruction ran over into the next frame
+    credit: i64,
+}
+
+impl VipTiming {
+    pub fn new() -> VipTiming {
+        VipTiming { credit: 0 }
+    }
+
+    // Runs one frame with Chip8::step, returns the instructions executed
+    pub fn run_frame(&mut self, chip8: &mut Chip8) -> u32 {
+        self.run_frame_with(chip8, Chip8::step)
+    }
+
+    // Runs one frame, executing each instruction with step, e.g. to trace
+    // it. step returns whether an instruction was executed.
+    //
+    // DXYN waits for the vertical blank, it only runs first in a frame.
+    // Cycles left while waiting for it, a key or after a fault are lost.
+    pub fn run_frame_with<F>(&mut self, chip8: &mut Chip8, mut step: F) -> u32
+    where
+        F: FnMut(&mut Chip8) -> bool,
+    {
+        self.credit += (CYCLES_PER_FRAME - FRAME_OVERHEAD) as i64;
+        let mut executed = 0;
+
+        while self.credit > 0 {
+            let pc = chip8.pc();
+            let opcode = (chip8.peek(pc) as usize) << 8 | chip8.peek(pc + 1) as usize;
+            let instruction = Instruction::decode(opcode);
+
+            if executed > 0 && matches!(instruction, Some(Instruction::Drw(..))) {
+                self.credit = 0;
+                break;
+            }
+
+            if !step(chip8) {
+                self.credit = 0;
+                break;
+            }
+            executed += 1;
+
+            let skipped = chip8.pc() == pc + 4;
+            // Unknown opcodes panic in the core, so this is always known
+            if let Some(instruction) = instruction {
+                self.credit -= cycles(instruction, skipped) as i64;
+            }
+        }
+
+        chip8.tick_timers();
+        executed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chip8(program: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(program).unwrap();
+        chip8.set_timers_per_frame(true);
+        chip8
+    }
+
+    #[test]
+    fn test_budget() {
+        // 1200 jumps to itself, 23 cycles each
+        let mut chip8 = chip8(&[0x12, 0x00]);
+        let mut timing = VipTiming::new();
+
+        // 2544 cycles: 110 jumps leave 14 cycles, the 111th runs over by 9
+        assert_eq!(timing.run_frame(&mut chip8), 111);
+        assert_eq!(timing.credit, -9);
+
+        // The overshoot is taken from the next frame
+        assert_eq!(timing.run_frame(&mut chip8), 111);
+        assert_eq!(timing.credit, -18);
+    }
+
+    #[test]
+    fn test_draw_waits_for_vblank() {
+        // Draws in a loop
+        let mut chip8 = chip8(&[0x60, 0x01, 0xD0, 0x05, 0x12, 0x02]);
+        let mut timing = VipTiming::new();
+
+        // 6001 runs, DXYN waits for the next frame
+        assert_eq!(timing.run_frame(&mut chip8), 1);
+        assert_eq!(chip8.pc(), 0x202);
+
+        // One draw per frame
+        assert_eq!(timing.run_frame(&mut chip8), 2);
+        assert_eq!(timing.run_frame(&mut chip8), 2);
+        assert_eq!(chip8.pc(), 0x202);
+    }
+
+    #[test]
+    fn test_timers_per_frame() {
+        // Sets the delay timer to 10 then spins
+        let mut chip8 = chip8(&[0x60, 0x0A, 0xF0, 0x15, 0x12, 0x04]);
+        let mut timing = VipTiming::new();
+
+        timing.run_frame(&mut chip8);
+        assert_eq!(chip8.delay_timer(), 9);
+        timing.run_frame(&mut chip8);
+        assert_eq!(chip8.delay_timer(), 8);
+    }
+
+    #[test]
+    fn test_waiting_for_key() {
+        let mut chip8 = chip8(&[0xF0, 0x0A, 0x12, 0x02]);
+        let mut timing = VipTiming::new();
+
+        assert_eq!(timing.run_frame(&mut chip8), 1);
+        assert_eq!(timing.run_frame(&mut chip8), 0);
+        assert_eq!(timing.credit, 0);
+    }
+}
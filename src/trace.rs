@@ -0,0 +1,260 @@
+// Execution trace, one line per executed instruction:
+//
+//     0x0202  A22A  LD I, 0x22A           I: 000 -> 22A
+//
+// Registers that changed are listed with their value before and after.
+use crate::chip8::{Chip8, MEM_SIZE, REG_SIZE};
+use crate::disasm::Instruction;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+
+// Registers before or after an instruction
+struct Registers {
+    v: [u8; REG_SIZE],
+    i: usize,
+    sp: usize,
+}
+
+impl Registers {
+    fn of(chip8: &Chip8) -> Registers {
+        Registers {
+            v: *chip8.v(),
+            i: chip8.i(),
+            sp: chip8.sp(),
+        }
+    }
+}
+
+pub struct Tracer<W: Write> {
+    out: W,
+
+    // Only instructions at these addresses are traced
+    range: RangeInclusive<usize>,
+
+    // With a ring buffer, only the last entries are kept and they are
+    // written when the core fails. Otherwise every entry is written.
+    ring: Option<VecDeque<String>>,
+    ring_size: usize,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W) -> Tracer<W> {
+        Tracer {
+            out,
+            range: 0..=MEM_SIZE - 1,
+            ring: None,
+            ring_size: 0,
+        }
+    }
+
+    pub fn set_range(&mut self, range: RangeInclusive<usize>) {
+        self.range = range;
+    }
+
+    // Keeps only the last n entries, 0 writes every entry again
+    pub fn set_ring_size(&mut self, n: usize) {
+        self.ring = if n > 0 {
+            Some(VecDeque::with_capacity(n))
+        } else {
+            None
+        };
+        self.ring_size = n;
+    }
+
+    // Executes one instruction like Chip8::step, tracing it.
+    // If the core faults or panics, e.g. on an unknown opcode, the ring
+    // buffer is written with the failing instruction. Panics continue.
+    pub fn step(&mut self, chip8: &mut Chip8) -> io::Result<bool> {
+        let pc = chip8.pc();
+        if !self.range.contains(&pc) {
+            return Ok(chip8.step());
+        }
+
+        let opcode = (chip8.peek(pc) as usize) << 8 | chip8.peek(pc + 1) as usize;
+        let before = Registers::of(chip8);
+
+        let mut entry = format!("0x{:04X}  {:04X}  ", pc, opcode);
+        match Instruction::decode(opcode) {
+            Some(instruction) => write!(entry, "{:<20}", instruction.to_string()),
+            None => write!(entry, "{:<20}", "???"),
+        }
+        .unwrap();
+
+        let executed = match panic::catch_unwind(AssertUnwindSafe(|| chip8.step())) {
+            Ok(executed) => executed,
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                entry.push_str("  !! ");
+                entry.push_str(&message);
+
+                self.push(entry)?;
+                self.dump()?;
+                panic::resume_unwind(payload);
+            }
+        };
+
+        if let Some(fault) = chip8.fault() {
+            write!(entry, "  !! {}", fault).unwrap();
+            self.push(entry)?;
+            self.dump()?;
+            return Ok(false);
+        }
+
+        // Waiting for a key, nothing happened
+        if !executed {
+            return Ok(false);
+        }
+
+        let after = Registers::of(chip8);
+        for reg in 0..REG_SIZE {
+            if before.v[reg] != after.v[reg] {
+                write!(
+                    entry,
+                    "  V{:X}: {:02X} -> {:02X}",
+                    reg, before.v[reg], after.v[reg]
+                )
+                .unwrap();
+            }
+        }
+        if before.i != after.i {
+            write!(entry, "  I: {:03X} -> {:03X}", before.i, after.i).unwrap();
+        }
+        if before.sp != after.sp {
+            write!(entry, "  SP: {} -> {}", before.sp, after.sp).unwrap();
+        }
+
+        self.push(entry.trim_end().to_string())?;
+        Ok(true)
+    }
+
+    // Writes the entries in the ring buffer, oldest first
+    pub fn dump(&mut self) -> io::Result<()> {
+        if let Some(ring) = &mut self.ring {
+            for entry in ring.drain(..) {
+                writeln!(self.out, "{}", entry)?;
+            }
+        }
+
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn push(&mut self, entry: String) -> io::Result<()> {
+        match &mut self.ring {
+            Some(ring) => {
+                if ring.len() == self.ring_size {
+                    ring.pop_front();
+                }
+                ring.push_back(entry);
+                Ok(())
+            }
+            None => writeln!(self.out, "{}", entry),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(tracer: Tracer<Vec<u8>>) -> Vec<String> {
+        let out = String::from_utf8(tracer.into_inner()).unwrap();
+        out.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut chip8 = Chip8::init();
+        chip8
+            .load_rom(&[0x6A, 0x42, 0xA3, 0x21, 0x22, 0x08, 0x00, 0x00, 0x00, 0xEE])
+            .unwrap();
+
+        let mut tracer = Tracer::new(Vec::new());
+        for _ in 0..4 {
+            assert!(tracer.step(&mut chip8).unwrap());
+        }
+
+        assert_eq!(
+            lines(tracer),
+            [
+                "0x0200  6A42  LD VA, 0x42           VA: 00 -> 42",
+                "0x0202  A321  LD I, 0x321           I: 000 -> 321",
+                "0x0204  2208  CALL 0x208            SP: 0 -> 1",
+                "0x0208  00EE  RET                   SP: 1 -> 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range() {
+        let mut chip8 = Chip8::init();
+        chip8
+            .load_rom(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03])
+            .unwrap();
+
+        let mut tracer = Tracer::new(Vec::new());
+        tracer.set_range(0x202..=0x203);
+        for _ in 0..3 {
+            tracer.step(&mut chip8).unwrap();
+        }
+
+        let lines = lines(tracer);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("0x0202"));
+        assert_eq!(chip8.v()[2], 3);
+    }
+
+    #[test]
+    fn test_ring_dumped_on_fault() {
+        let mut chip8 = Chip8::init();
+        chip8.load_rom(&[0x60, 0x01, 0x00, 0xEE]).unwrap();
+
+        let mut tracer = Tracer::new(Vec::new());
+        tracer.set_ring_size(8);
+        assert!(tracer.step(&mut chip8).unwrap());
+        assert!(!tracer.step(&mut chip8).unwrap());
+
+        let lines = lines(tracer);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            "0x0202  00EE  RET                   !! Stack underflow"
+        );
+    }
+
+    #[test]
+    fn test_ring_dumped_on_panic() {
+        let mut chip8 = Chip8::init();
+        chip8
+            .load_rom(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x01, 0x23])
+            .unwrap();
+
+        let mut tracer = Tracer::new(Vec::new());
+        tracer.set_ring_size(2);
+        for _ in 0..3 {
+            tracer.step(&mut chip8).unwrap();
+        }
+        assert!(tracer.out.is_empty());
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| tracer.step(&mut chip8)));
+        assert!(result.is_err());
+
+        let lines = lines(tracer);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("0x0204  6203"));
+        assert_eq!(
+            lines[1],
+            "0x0206  0123  ???                   !! Unknown opcode: 0x0123!"
+        );
+    }
+}
@@ -0,0 +1,222 @@
+// WebAssembly binding of the core, without wasm-bindgen so it builds with
+// nothing but the wasm32-unknown-unknown target:
+//
+//     cargo build -p chip8_wasm --release --target wasm32-unknown-unknown
+//     cp target/wasm32-unknown-unknown/release/chip8_wasm.wasm wasm/www/
+//
+// Each wasm instance holds one emulator. JavaScript copies a ROM into
+// chip8_rom_buffer(), then reads the RGBA framebuffer from
+// chip8_framebuffer() after each chip8_run_frame(), see www/chip8.js.
+use chip8_core::chip8::{KEY_SIZE, MEM_SIZE, PC_START};
+use chip8_core::palette::Palette;
+use chip8_core::{Chip8, SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::cell::RefCell;
+
+const MAX_ROM_SIZE: usize = MEM_SIZE - PC_START;
+const RGBA_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT * 4;
+
+struct Emulator {
+    chip8: Chip8,
+
+    // Filled by JavaScript before chip8_load_rom()
+    rom: [u8; MAX_ROM_SIZE],
+
+    // Framebuffer converted with the palette, read by JavaScript
+    rgba: [u8; RGBA_SIZE],
+
+    palette: Palette,
+}
+
+impl Emulator {
+    fn new() -> Emulator {
+        let mut emu = Emulator {
+            chip8: Chip8::init(),
+            rom: [0; MAX_ROM_SIZE],
+            rgba: [0; RGBA_SIZE],
+            palette: Palette::DEFAULT,
+        };
+        emu.update_rgba();
+
+        emu
+    }
+
+    fn update_rgba(&mut self) {
+        for (i, pixel) in self.chip8.screen().iter().enumerate() {
+            let [r, g, b] = self.palette.color(*pixel);
+            self.rgba[i * 4..i * 4 + 4].copy_from_slice(&[r, g, b, 0xFF]);
+        }
+    }
+}
+
+thread_local! {
+    static EMULATOR: RefCell<Emulator> = RefCell::new(Emulator::new());
+}
+
+fn with_emulator<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut Emulator) -> R,
+{
+    EMULATOR.with(|emu| f(&mut emu.borrow_mut()))
+}
+
+// Powers the emulator off and on again, clearing the loaded ROM
+#[no_mangle]
+pub extern "C" fn chip8_reset(seed: u32) {
+    with_emulator(|emu| {
+        emu.chip8 = Chip8::init();
+        emu.chip8.set_seed(seed as u64);
+        emu.update_rgba();
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_rom_buffer() -> *mut u8 {
+    with_emulator(|emu| emu.rom.as_mut_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_rom_buffer_size() -> usize {
+    MAX_ROM_SIZE
+}
+
+// Loads the first size bytes of the ROM buffer, returns false if too large
+#[no_mangle]
+pub extern "C" fn chip8_load_rom(size: usize) -> bool {
+    with_emulator(|emu| {
+        if size > MAX_ROM_SIZE {
+            return false;
+        }
+
+        let rom = emu.rom;
+        // chip8_run_frame() is called at 60 Hz, the timers tick once per call
+        emu.chip8.set_timers_per_frame(true);
+        emu.chip8.load_rom(&rom[..size]).is_ok()
+    })
+}
+
+// Executes one 60 Hz frame, returns the number of executed instructions
+#[no_mangle]
+pub extern "C" fn chip8_run_frame(instructions: u32) -> u32 {
+    with_emulator(|emu| {
+        let executed = emu.chip8.run_frame(instructions);
+
+        if emu.chip8.draw_flag() {
+            emu.update_rgba();
+            emu.chip8.clear_draw_flag();
+        }
+
+        executed
+    })
+}
+
+// RGBA framebuffer, SCREEN_WIDTH x SCREEN_HEIGHT
+#[no_mangle]
+pub extern "C" fn chip8_framebuffer() -> *const u8 {
+    with_emulator(|emu| emu.rgba.as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_screen_width() -> usize {
+    SCREEN_WIDTH
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_screen_height() -> usize {
+    SCREEN_HEIGHT
+}
+
+// Presses or releases key 0x0 - 0xF, other keys are ignored
+#[no_mangle]
+pub extern "C" fn chip8_set_key(key: u32, pressed: bool) {
+    if (key as usize) < KEY_SIZE {
+        with_emulator(|emu| emu.chip8.set_key(key as usize, pressed));
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn chip8_is_beeping() -> bool {
+    with_emulator(|emu| emu.chip8.is_beeping())
+}
+
+// Colors as 0xRRGGBB
+#[no_mangle]
+pub extern "C" fn chip8_set_palette(off: u32, on: u32) {
+    let rgb = |c: u32| [(c >> 16) as u8, (c >> 8) as u8, c as u8];
+
+    with_emulator(|emu| {
+        emu.palette = Palette {
+            off: rgb(off),
+            on: rgb(on),
+        };
+        emu.update_rgba();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::slice;
+
+    fn load(rom: &[u8]) -> bool {
+        let buffer = unsafe { slice::from_raw_parts_mut(chip8_rom_buffer(), MAX_ROM_SIZE) };
+        buffer[..rom.len()].copy_from_slice(rom);
+
+        chip8_load_rom(rom.len())
+    }
+
+    fn framebuffer() -> Vec<u8> {
+        unsafe { slice::from_raw_parts(chip8_framebuffer(), RGBA_SIZE).to_vec() }
+    }
+
+    #[test]
+    fn test_load_rom_too_large() {
+        assert!(!chip8_load_rom(chip8_rom_buffer_size() + 1));
+    }
+
+    #[test]
+    fn test_run_frame() {
+        chip8_reset(1);
+
+        // Set the delay timer to 10, draw the 0 glyph at (0, 0), then
+        // loop forever
+        assert!(load(&[
+            0x61, 0x0A, 0xF1, 0x15, 0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x0A
+        ]));
+        assert_eq!(chip8_run_frame(6), 6);
+
+        // The timers tick once per frame, not per instruction
+        with_emulator(|emu| {
+            assert_eq!(emu.chip8.delay_timer(), 9);
+            emu.chip8.step();
+            assert_eq!(emu.chip8.delay_timer(), 9);
+        });
+
+        let rgba = framebuffer();
+        // Top left pixel of the glyph is set, opaque white
+        assert_eq!(rgba[0..4], [0xFF, 0xFF, 0xFF, 0xFF]);
+        // 5th pixel of the first row is unset
+        assert_eq!(rgba[16..20], [0x00, 0x00, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn test_set_palette() {
+        chip8_reset(1);
+        chip8_set_palette(0x102030, 0xFFFFFF);
+
+        assert_eq!(framebuffer()[0..4], [0x10, 0x20, 0x30, 0xFF]);
+    }
+
+    #[test]
+    fn test_set_key() {
+        chip8_reset(1);
+
+        // Wait for a key into v0, then skip the next instruction if v0 is pressed
+        assert!(load(&[0xF0, 0x0A, 0xE0, 0x9E]));
+        assert_eq!(chip8_run_frame(2), 1);
+
+        chip8_set_key(0x5, true);
+        chip8_set_key(0x20, true); // Ignored
+        assert_eq!(chip8_run_frame(1), 1);
+        with_emulator(|emu| assert_eq!(emu.chip8.pc(), PC_START + 6));
+    }
+}
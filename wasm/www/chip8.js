@@ -0,0 +1,64 @@
+// JavaScript binding of chip8_wasm.wasm, works in browsers and node
+export const KEYMAP = {
+  x: 0x0, 1: 0x1, 2: 0x2, 3: 0x3,
+  q: 0x4, w: 0x5, e: 0x6, a: 0x7,
+  s: 0x8, d: 0x9, z: 0xa, c: 0xb,
+  4: 0xc, r: 0xd, f: 0xe, v: 0xf,
+};
+
+export class Chip8 {
+  // bytes: contents of chip8_wasm.wasm
+  static async load(bytes) {
+    const { instance } = await WebAssembly.instantiate(bytes, {});
+    return new Chip8(instance.exports);
+  }
+
+  constructor(exports) {
+    this.exports = exports;
+    this.width = exports.chip8_screen_width();
+    this.height = exports.chip8_screen_height();
+    this.reset();
+  }
+
+  reset() {
+    this.exports.chip8_reset((Math.random() * 0x100000000) >>> 0);
+  }
+
+  // rom: Uint8Array, throws if it doesn't fit in memory
+  loadRom(rom) {
+    const size = this.exports.chip8_rom_buffer_size();
+    if (rom.length > size) {
+      throw new Error(`ROM is too large: ${rom.length} bytes, at most ${size} bytes fit`);
+    }
+
+    const buffer = new Uint8Array(this.exports.memory.buffer, this.exports.chip8_rom_buffer(), size);
+    buffer.set(rom);
+    this.exports.chip8_load_rom(rom.length);
+  }
+
+  runFrame(instructions) {
+    return this.exports.chip8_run_frame(instructions);
+  }
+
+  // RGBA pixels, valid until the next call into the emulator
+  framebuffer() {
+    return new Uint8ClampedArray(
+      this.exports.memory.buffer,
+      this.exports.chip8_framebuffer(),
+      this.width * this.height * 4,
+    );
+  }
+
+  setKey(key, pressed) {
+    this.exports.chip8_set_key(key, pressed);
+  }
+
+  isBeeping() {
+    return this.exports.chip8_is_beeping() !== 0;
+  }
+
+  // Colors as 0xRRGGBB
+  setPalette(off, on) {
+    this.exports.chip8_set_palette(off, on);
+  }
+}